@@ -1,20 +1,35 @@
-use crate::edit_distance::levenshtein_distance;
+use crate::clustering::Clustering;
+use crate::constraints::{Constraints, RowConstraints};
+use crate::dialect::{delimiter_name, CsvDialect, DELIMITERS};
+use crate::edit_distance::{LengthUnit, Metric};
+use crate::encoding::TextEncoding;
+use crate::export::{write_groups, ExportFormat};
+use crate::grouping::{
+    compared_columns, exact_column_keys, preview_table, read_table_with_progress, ColumnMatch,
+    DuplicateKinds, GroupStats, GroupingConfig, Table,
+};
+use crate::linkage::{link_keys, write_linked, LinkageConfig};
+use crate::normalize::Normalization;
+use crate::phonetic::Phonetic;
+use crate::progress::Progress;
+use crate::review::Review;
+use crate::scores::{ScoreCache, SCORE_MARGIN};
+use crate::spreadsheet::{
+    is_spreadsheet, preview_sheet, read_sheet, sheet_names, SheetOptions, SPREADSHEET_EXTENSIONS,
+};
+use crate::survivorship::{write_merged, MergeConfig, Survivorship};
+use crate::xlsx::write_xlsx;
 use egui::RichText;
 use egui_extras::{Column, Size, StripBuilder, TableBuilder};
 use poll_promise::Promise;
+use std::collections::BTreeSet;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
-use csv;
 use rfd::FileDialog;
 
-#[derive(Debug)]
-struct Table {
-    file: PathBuf,
-    headers: Vec<String>,
-    rows: Vec<Vec<String>>,
-}
-
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct TableSettings {
     striped: bool,
@@ -50,31 +65,276 @@ impl LogMessage {
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)] // settings saved before a field was added keep the others
 struct EditDistanceSettings {
     col_idx: usize,
-    similarity: usize,
+    similarity: f64,
     case_sensitive: bool,
+    metric: Metric,
+    unit: LengthUnit,
+    threads: usize,
+    columns: Vec<ColumnMatch>,
+    clustering: Clustering,
+    drop_singletons: bool,
+    normalization: Normalization,
+    phonetic: Phonetic,
+    /// The column identifying the rows, keying the saved review decisions.
+    id_column: Option<usize>,
 }
 
 impl Default for EditDistanceSettings {
     fn default() -> Self {
         Self {
             col_idx: 0,
-            similarity: 100,
+            similarity: 100.0,
             case_sensitive: true,
+            metric: Metric::Levenshtein,
+            unit: LengthUnit::Graphemes,
+            threads: 0,
+            columns: Vec::new(),
+            clustering: Clustering::Greedy,
+            drop_singletons: false,
+            normalization: Normalization::default(),
+            phonetic: Phonetic::Off,
+            id_column: None,
+        }
+    }
+}
+
+impl EditDistanceSettings {
+    /// Drop the columns a newly loaded table with `columns` columns does not
+    /// have, they were saved with the settings of another table.
+    fn fit_columns(&mut self, columns: usize) {
+        if self.col_idx >= columns {
+            self.col_idx = 0;
+        }
+        self.columns.retain(|c| c.col_idx < columns);
+        self.id_column = self.id_column.filter(|&idx| idx < columns);
+    }
+
+    fn grouping_config(&self) -> GroupingConfig {
+        GroupingConfig {
+            col_idx: self.col_idx,
+            similarity: self.similarity,
+            case_sensitive: self.case_sensitive,
+            metric: self.metric,
+            unit: self.unit,
+            threads: self.threads,
+            columns: self.columns.clone(),
+            clustering: self.clustering,
+            drop_singletons: self.drop_singletons,
+            normalization: self.normalization.clone(),
+            phonetic: self.phonetic,
+            constraints: Default::default(),
         }
     }
 }
 
+/// Work running on another thread, with its progress.
+struct Task<T: Send + 'static> {
+    result: Promise<T>,
+    progress: Arc<Progress>,
+}
+
+impl<T: Send + 'static> Task<T> {
+    /// Run `work` on a new thread, repainting once it is done.
+    fn spawn(ctx: &egui::Context, work: impl FnOnce(&Progress) -> T + Send + 'static) -> Self {
+        let ctx = ctx.clone();
+        let (sender, result) = Promise::new();
+        let progress = Arc::new(Progress::new());
+        let worker_progress = progress.clone();
+        thread::spawn(move || {
+            sender.send(work(&worker_progress));
+            ctx.request_repaint();
+        });
+        Self { result, progress }
+    }
+
+    /// The result once the work is done, the task itself before.
+    fn try_take(self) -> Result<T, Self> {
+        let progress = self.progress;
+        self.result
+            .try_take()
+            .map_err(|result| Self { result, progress })
+    }
+}
+
+/// Groups with the similarities they were found from and the exact keys of
+/// the rows.
+type Grouping = (Vec<Vec<usize>>, ScoreCache, Vec<usize>);
+
+#[derive(Default)]
 struct ResultWindow {
     open: bool,
-    indices: Option<Promise<Vec<Vec<usize>>>>,
+    /// The grouping running in the background, `None` once cancelled. The
+    /// result below is kept until it is done.
+    task: Option<Task<Option<Grouping>>>,
+    /// The similarities calculated for the last groups, regrouped from when
+    /// only the similarity changes.
+    scores: Option<ScoreCache>,
+    /// The exact keys of the rows as the scores compare them, telling exact
+    /// duplicates from fuzzy ones.
+    exact_keys: Vec<usize>,
+    /// The decisions of earlier reviews the scores were grouped with, loaded
+    /// once per grouping and honoured again when regrouping.
+    constraints: RowConstraints,
+    /// The result as edited by the reviewer, once it is ready.
+    review: Option<Review>,
+    /// Groups selected for merging.
+    selected: BTreeSet<usize>,
 }
-impl Default for ResultWindow {
-    fn default() -> Self {
-        Self {
-            open: false,
-            indices: None,
+
+/// For every left row, the matching right rows with their similarity.
+type Matches = Vec<Vec<(usize, f64)>>;
+
+/// Matching the rows of the loaded table, the left one, against the rows of
+/// a second table.
+#[derive(Default)]
+struct LinkageWindow {
+    open: bool,
+    right: Option<Table>,
+    config: LinkageConfig,
+    /// The linkage running in the background, `None` once cancelled.
+    task: Option<Task<Option<Matches>>>,
+    matches: Option<Matches>,
+}
+
+impl LinkageWindow {
+    /// Drop the matches of tables or keys that changed.
+    fn reset(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.progress.cancel();
+        }
+        self.matches = None;
+    }
+}
+
+/// Matches shown by the record linkage window, the export has all of them.
+const SHOWN_MATCHES: usize = 200;
+
+/// An edit of the result picked in the result table.
+enum ReviewAction {
+    Confirm(usize),
+    RemoveRow(usize, usize),
+    NotDuplicate(usize),
+}
+
+/// Rows shown by the import dialog.
+const PREVIEW_ROWS: usize = 20;
+
+/// How the picked file is read.
+enum ImportFormat {
+    Csv(CsvDialect),
+    Spreadsheet {
+        sheets: Vec<String>,
+        options: SheetOptions,
+    },
+}
+
+/// Where a loaded table goes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ImportTarget {
+    /// The table grouped, and the left table of record linkage.
+    Table,
+    /// The right table of record linkage.
+    LinkRight,
+}
+
+/// The preview of a sheet read in the background, with the sheets of the
+/// workbook when they were not listed yet.
+type SheetPreview = Task<Result<(Option<Vec<String>>, Table), std::io::Error>>;
+
+/// How a picked file is read, previewed before the file is loaded.
+struct ImportDialog {
+    file: PathBuf,
+    target: ImportTarget,
+    format: ImportFormat,
+    preview: Result<Table, String>,
+    /// The preview of a workbook being read, replacing `preview` once read.
+    /// Some workbooks are read whole, which takes too long for the UI thread.
+    reading: Option<SheetPreview>,
+}
+
+impl ImportDialog {
+    fn new(ctx: &egui::Context, file: PathBuf, target: ImportTarget) -> Self {
+        let format = if is_spreadsheet(&file) {
+            // The first sheet until the sheets are listed with the preview
+            ImportFormat::Spreadsheet {
+                sheets: Vec::new(),
+                options: SheetOptions::default(),
+            }
+        } else {
+            ImportFormat::Csv(CsvDialect::sniff_file(&file).unwrap_or_default())
+        };
+        let mut dialog = Self {
+            file,
+            target,
+            format,
+            preview: Err(String::new()),
+            reading: None,
+        };
+        dialog.refresh(ctx);
+        dialog
+    }
+
+    fn refresh(&mut self, ctx: &egui::Context) {
+        match &self.format {
+            ImportFormat::Csv(dialect) => {
+                self.preview = preview_table(self.file.clone(), dialect, PREVIEW_ROWS)
+                    .map_err(|e| e.to_string());
+            }
+            ImportFormat::Spreadsheet { sheets, options } => {
+                let file = self.file.clone();
+                let options = options.clone();
+                let list_sheets = sheets.is_empty();
+                // A preview still being read is outdated, it is dropped once read
+                self.reading = Some(Task::spawn(ctx, move |_| {
+                    let sheets = if list_sheets {
+                        Some(sheet_names(&file)?)
+                    } else {
+                        None
+                    };
+                    preview_sheet(file, &options, PREVIEW_ROWS).map(|preview| (sheets, preview))
+                }));
+            }
+        }
+    }
+
+    /// Show the preview read in the background once it is read.
+    fn poll(&mut self) {
+        let Some(task) = self.reading.take() else {
+            return;
+        };
+        let result = match task.try_take() {
+            Ok(result) => result,
+            Err(task) => {
+                self.reading = Some(task);
+                return;
+            }
+        };
+        self.preview = match result {
+            Ok((listed, preview)) => {
+                if let (ImportFormat::Spreadsheet { sheets, options }, Some(listed)) =
+                    (&mut self.format, listed)
+                {
+                    if options.sheet.is_empty() {
+                        options.sheet = listed.first().cloned().unwrap_or_default();
+                    }
+                    *sheets = listed;
+                }
+                Ok(preview)
+            }
+            Err(e) => Err(e.to_string()),
+        };
+    }
+
+    /// Read the file, counting the bytes read from csv files in `progress`.
+    fn read(&self, progress: &Progress) -> Result<Table, std::io::Error> {
+        match &self.format {
+            ImportFormat::Csv(dialect) => {
+                read_table_with_progress(self.file.clone(), dialect, progress)
+            }
+            ImportFormat::Spreadsheet { options, .. } => read_sheet(self.file.clone(), options),
         }
     }
 }
@@ -93,15 +353,33 @@ pub struct TemplateApp {
     #[serde(skip)]
     table: Option<Table>,
 
+    #[serde(skip)]
+    import_dialog: Option<ImportDialog>,
+
+    /// The table being read in the background, shown once fully read.
+    #[serde(skip)]
+    loading: Option<Task<(ImportTarget, Result<Table, std::io::Error>)>>,
+
     table_settings: TableSettings,
 
     edit_distance_settings: EditDistanceSettings,
 
+    merge_config: MergeConfig,
+
+    /// Text encoding of the exported files.
+    export_encoding: TextEncoding,
+
+    /// Format of the grouped rows exported by the Export button.
+    export_format: ExportFormat,
+
     #[serde(skip)]
     logs: Vec<LogMessage>,
 
     #[serde(skip)]
     result_window: ResultWindow,
+
+    #[serde(skip)]
+    linkage: LinkageWindow,
 }
 
 impl Default for TemplateApp {
@@ -111,10 +389,16 @@ impl Default for TemplateApp {
             label: "Hello World!".to_owned(),
             value: 2.7,
             table: None,
+            import_dialog: None,
+            loading: None,
             table_settings: Default::default(),
             edit_distance_settings: Default::default(),
+            merge_config: Default::default(),
+            export_encoding: Default::default(),
+            export_format: Default::default(),
             logs: Vec::new(),
             result_window: Default::default(),
+            linkage: Default::default(),
         }
     }
 }
@@ -137,6 +421,376 @@ impl TemplateApp {
 }
 
 impl TemplateApp {
+    /// Pick a csv file or a workbook and open the import dialog on it.
+    fn pick_table_file(&mut self, ctx: &egui::Context, target: ImportTarget) {
+        let csv_extensions = ["csv", "tsv", "txt"];
+        let all_extensions: Vec<&str> = csv_extensions
+            .iter()
+            .chain(SPREADSHEET_EXTENSIONS.iter())
+            .copied()
+            .collect();
+        let file = FileDialog::new()
+            .add_filter("Tables", &all_extensions)
+            .add_filter("csv", &csv_extensions)
+            .add_filter("Spreadsheets", &SPREADSHEET_EXTENSIONS)
+            .set_directory("/")
+            .pick_file();
+        if let Some(file) = file {
+            self.import_dialog = Some(ImportDialog::new(ctx, file, target));
+        }
+    }
+
+    /// Whether the calculated similarities cover the loaded table and the
+    /// current settings.
+    fn scores_cover_settings(&self) -> bool {
+        match (&self.table, &self.result_window.scores) {
+            (Some(t), Some(scores)) => {
+                scores.covers(t, &self.edit_distance_settings.grouping_config())
+            }
+            _ => false,
+        }
+    }
+
+    /// Group the loaded table: from the calculated similarities when they
+    /// cover the settings, by comparing the rows in the background otherwise.
+    /// A grouping still running is cancelled.
+    fn cal_similarity(&mut self, ctx: &egui::Context) {
+        let Some(t) = &self.table else {
+            return;
+        };
+        let mut config = self.edit_distance_settings.grouping_config();
+        if let Err(e) = config.check_columns(t) {
+            self.logs.push(LogMessage::new(e, LogLevel::Error));
+            return;
+        }
+        // Honour the decisions of earlier reviews
+        let constraints_file = Constraints::sidecar_path(&t.file);
+        if constraints_file.exists() {
+            match Constraints::load(&constraints_file)
+                .map_err(|e| e.to_string())
+                .and_then(|c| c.resolve(t))
+            {
+                Ok(constraints) => config.constraints = constraints,
+                Err(e) => self.logs.push(LogMessage::new(
+                    format!("Failed to load {:?}: {}", constraints_file, e),
+                    LogLevel::Error,
+                )),
+            }
+        }
+        self.result_window.constraints = config.constraints.clone();
+        if let Some(task) = self.result_window.task.take() {
+            task.progress.cancel();
+        }
+
+        if let Some(scores) = &self.result_window.scores {
+            if scores.covers(t, &config) {
+                if self.result_window.review.is_some() {
+                    self.regroup();
+                } else {
+                    self.result_window.review = Some(Review::new(scores.group(&config)));
+                    self.result_window.selected.clear();
+                }
+                self.result_window.open = true;
+                return;
+            }
+        }
+        let columns = compared_columns(t, &config);
+        let file = t.file.clone();
+        let floor = (config.similarity - SCORE_MARGIN).max(0.0);
+        self.result_window.task = Some(Task::spawn(ctx, move |progress| {
+            ScoreCache::from_columns(file, &columns, &config, floor, progress).map(|scores| {
+                let exact_keys = exact_column_keys(&columns, &config);
+                (scores.group(&config), scores, exact_keys)
+            })
+        }));
+    }
+
+    /// Regroup the result from the calculated similarities at the current
+    /// settings, keeping the edits of the review: its decisions are honoured
+    /// along with those loaded for the grouping.
+    fn regroup(&mut self) {
+        let (Some(scores), Some(review)) =
+            (&self.result_window.scores, &mut self.result_window.review)
+        else {
+            return;
+        };
+        if let Some(task) = self.result_window.task.take() {
+            task.progress.cancel();
+        }
+        let mut config = self.edit_distance_settings.grouping_config();
+        config.constraints = self.result_window.constraints.clone();
+        config.constraints.extend(review.decisions());
+        review.regroup(scores.group(&config));
+        self.result_window.selected.clear();
+    }
+
+    fn linkage_window_ui(&mut self, ctx: &egui::Context) {
+        if let Some(task) = self.linkage.task.take() {
+            match task.try_take() {
+                Ok(Some(matches)) => self.linkage.matches = Some(matches),
+                Ok(None) => self.logs.push(LogMessage::new(
+                    String::from("Linkage cancelled"),
+                    LogLevel::Info,
+                )),
+                Err(task) => self.linkage.task = Some(task),
+            }
+        }
+
+        let mut open = self.linkage.open;
+        let mut pick_right = false;
+        let mut start = false;
+        let mut export = false;
+        egui::Window::new("Record linkage")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                let Some(left) = &self.table else {
+                    ui.label("Open the left table first");
+                    return;
+                };
+                let linkage = &mut self.linkage;
+                let mut changed = false;
+                egui::Grid::new("linkage")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Left table");
+                        ui.label(left.file.to_str().unwrap_or("Err when parse file path"));
+                        ui.end_row();
+
+                        ui.label("Right table");
+                        ui.horizontal(|ui| {
+                            if let Some(right) = &linkage.right {
+                                ui.label(right.file.to_str().unwrap_or("Err when parse file path"));
+                            }
+                            pick_right = ui.button("Open").clicked();
+                        });
+                        ui.end_row();
+
+                        ui.label("Left key");
+                        changed |= column_combo(
+                            ui,
+                            "left_key",
+                            &mut linkage.config.left_column,
+                            &left.headers,
+                        );
+                        ui.end_row();
+
+                        if let Some(right) = &linkage.right {
+                            ui.label("Right key");
+                            changed |= column_combo(
+                                ui,
+                                "right_key",
+                                &mut linkage.config.right_column,
+                                &right.headers,
+                            );
+                            ui.end_row();
+                        }
+
+                        ui.label("Matches per row");
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(&mut linkage.config.max_matches)
+                                    .clamp_range(0..=100)
+                                    .speed(0.1),
+                            )
+                            .on_hover_text("The best matches kept for every left row, 0 keeps all of them")
+                            .changed();
+                        ui.end_row();
+                    });
+                if changed {
+                    linkage.reset();
+                }
+                ui.label(
+                    RichText::new("Keys are compared with the similarity, metric, unit, normalization, phonetic and case settings of the side panel")
+                        .small()
+                        .weak(),
+                );
+
+                ui.horizontal(|ui| {
+                    start = ui
+                        .add_enabled(linkage.right.is_some(), egui::Button::new("Link"))
+                        .clicked();
+                    export = ui
+                        .add_enabled(linkage.matches.is_some(), egui::Button::new("Export"))
+                        .on_hover_text("Every left row joined with its matches, in the export encoding")
+                        .clicked();
+                });
+                if let Some(task) = &linkage.task {
+                    let progress = &task.progress;
+                    progress_ui(
+                        ui,
+                        progress,
+                        format!("{}/{} pairs checked", progress.done(), progress.total()),
+                        "Stop matching",
+                    );
+                }
+
+                let (Some(right), Some(matches)) = (&linkage.right, &linkage.matches) else {
+                    return;
+                };
+                let matched = matches.iter().filter(|m| !m.is_empty()).count();
+                let pairs: usize = matches.iter().map(Vec::len).sum();
+                ui.label(format!(
+                    "{} of {} left rows matched, {} matches",
+                    matched,
+                    left.rows.len(),
+                    pairs
+                ));
+                ui.separator();
+                let (left_column, right_column) =
+                    (linkage.config.left_column, linkage.config.right_column);
+                egui::ScrollArea::both().show(ui, |ui| {
+                    egui::Grid::new("linkage_matches").striped(true).show(ui, |ui| {
+                        ui.strong(&left.headers[left_column]);
+                        ui.strong("Similarity");
+                        ui.strong(&right.headers[right_column]);
+                        ui.end_row();
+                        let shown = matches
+                            .iter()
+                            .enumerate()
+                            .flat_map(|(l, m)| m.iter().map(move |&(r, s)| (l, r, s)))
+                            .take(SHOWN_MATCHES);
+                        for (l, r, similarity) in shown {
+                            ui.label(&left.rows[l][left_column]);
+                            ui.label(format!("{:.2}", similarity));
+                            ui.label(&right.rows[r][right_column]);
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        self.linkage.open = open;
+
+        if pick_right {
+            self.pick_table_file(ctx, ImportTarget::LinkRight);
+        }
+        let (Some(left), Some(right)) = (&self.table, &self.linkage.right) else {
+            return;
+        };
+        if start {
+            let config = self.edit_distance_settings.grouping_config();
+            let left_keys = left.keys(self.linkage.config.left_column);
+            let right_keys = right.keys(self.linkage.config.right_column);
+            let max_matches = self.linkage.config.max_matches;
+            // Not `reset`, the right table is borrowed
+            if let Some(task) = self.linkage.task.take() {
+                task.progress.cancel();
+            }
+            self.linkage.matches = None;
+            self.linkage.task = Some(Task::spawn(ctx, move |progress| {
+                link_keys(&left_keys, &right_keys, max_matches, &config, progress)
+            }));
+        }
+        if let (true, Some(matches)) = (export, &self.linkage.matches) {
+            let output = FileDialog::new().add_filter("csv", &["csv"]).save_file();
+            match output {
+                Some(f) => match write_linked(&f, left, right, matches, self.export_encoding) {
+                    Ok(_) => self.logs.push(LogMessage::new(
+                        format!("Exported to {:?}", f),
+                        LogLevel::Info,
+                    )),
+                    Err(e) => self.logs.push(LogMessage::new(
+                        format!("Failed to export to {:?}: {:?}", f, e),
+                        LogLevel::Error,
+                    )),
+                },
+                None => self.logs.push(LogMessage::new(
+                    String::from("Failed to select output"),
+                    LogLevel::Warning,
+                )),
+            }
+        }
+    }
+
+    fn import_dialog_ui(&mut self, ctx: &egui::Context) {
+        let dialog = match &mut self.import_dialog {
+            Some(dialog) => dialog,
+            None => return,
+        };
+        dialog.poll();
+        let mut open = true;
+        let mut load = false;
+        let mut cancel = false;
+        let title = match dialog.target {
+            ImportTarget::Table => "Import",
+            ImportTarget::LinkRight => "Import right table",
+        };
+        egui::Window::new(title)
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.label(dialog.file.to_str().unwrap_or("Err when parse file path"));
+                let mut changed = false;
+                egui::Grid::new("import_format")
+                    .num_columns(2)
+                    .show(ui, |ui| match &mut dialog.format {
+                        ImportFormat::Csv(dialect) => changed |= dialect_ui(ui, dialect),
+                        ImportFormat::Spreadsheet { sheets, options } => {
+                            changed |= sheet_options_ui(ui, sheets, options)
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    if let ImportFormat::Csv(dialect) = &mut dialog.format {
+                        if ui
+                            .button("Detect")
+                            .on_hover_text("Guess the dialect from the start of the file")
+                            .clicked()
+                        {
+                            *dialect = CsvDialect::sniff_file(&dialog.file).unwrap_or_default();
+                            changed = true;
+                        }
+                    }
+                    let ready = dialog.preview.is_ok() && dialog.reading.is_none();
+                    load = ui.add_enabled(ready, egui::Button::new("Load")).clicked();
+                    cancel = ui.button("Cancel").clicked();
+                    if dialog.reading.is_some() {
+                        ui.spinner();
+                        ui.label("Reading the preview");
+                    }
+                });
+                if changed {
+                    dialog.refresh(ctx);
+                }
+
+                ui.separator();
+                match &dialog.preview {
+                    Ok(preview) => {
+                        egui::ScrollArea::both().show(ui, |ui| {
+                            egui::Grid::new("csv_preview").striped(true).show(ui, |ui| {
+                                for header in &preview.headers {
+                                    ui.strong(header);
+                                }
+                                ui.end_row();
+                                for row in &preview.rows {
+                                    for value in row {
+                                        ui.label(value);
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                        });
+                    }
+                    Err(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
+                    }
+                }
+            });
+
+        if load {
+            if let Some(task) = self.loading.take() {
+                task.progress.cancel();
+            }
+            if let Some(dialog) = self.import_dialog.take() {
+                self.loading = Some(Task::spawn(ctx, move |progress| {
+                    (dialog.target, dialog.read(progress))
+                }));
+            }
+        }
+        if load || cancel || !open {
+            self.import_dialog = None;
+        }
+    }
+
     fn table_ui(&mut self, ui: &mut egui::Ui) {
         if self.table.is_none() {
             return;
@@ -153,6 +807,17 @@ impl TemplateApp {
         for _ in 0..t.headers.len() {
             table = table.column(Column::remainder());
         }
+        // Preview of the compared key once normalized and phonetically encoded
+        let config = self.edit_distance_settings.grouping_config();
+        let preview = if config.normalization.is_identity() && config.phonetic == Phonetic::Off {
+            None
+        } else {
+            let col_idx = config.columns.first().map_or(config.col_idx, |c| c.col_idx);
+            t.headers.get(col_idx).map(|header| (col_idx, header))
+        };
+        if preview.is_some() {
+            table = table.column(Column::remainder());
+        }
 
         if let Some(row_nr) = self.table_settings.scroll_to_row.take() {
             table = table.scroll_to_row(row_nr, None);
@@ -165,6 +830,11 @@ impl TemplateApp {
                         ui.strong(col);
                     });
                 }
+                if let Some((_, col)) = preview {
+                    header.col(|ui| {
+                        ui.strong(format!("{} (normalized)", col));
+                    });
+                }
             })
             .body(|body| {
                 let row_height = text_height * 1.2;
@@ -174,6 +844,12 @@ impl TemplateApp {
                             ui.label(col);
                         });
                     }
+                    if let Some((col_idx, _)) = preview {
+                        let key = t.rows[idx].get(col_idx).map_or("", |k| k.as_str());
+                        row.col(|ui| {
+                            ui.label(config.prepare_key(key));
+                        });
+                    }
                 })
             });
     }
@@ -199,27 +875,7 @@ impl eframe::App for TemplateApp {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
                     if ui.button("Open").clicked() {
-                        let file = FileDialog::new()
-                            .add_filter("csv", &["csv"])
-                            .set_directory("/")
-                            .pick_file();
-
-                        // parse
-                        if let Some(file) = file {
-                            match read_table(file) {
-                                Ok(t) => {
-                                    self.table = Some(t);
-                                }
-                                Err(e) => {
-                                    // Failed to parse the csv file
-                                    println!("Failed to parse the csv file, {:?}", e);
-                                    self.logs.push(LogMessage::new(
-                                        String::from("Failed to parse the csv file"),
-                                        LogLevel::Error,
-                                    ));
-                                }
-                            }
-                        }
+                        self.pick_table_file(ctx, ImportTarget::Table);
                         ui.close_menu();
                     }
                     if ui.button("Quit").clicked() {
@@ -288,37 +944,34 @@ impl eframe::App for TemplateApp {
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
+            let mut similarity_changed = false;
             egui::Grid::new("table_settings")
                 .num_columns(2)
                 .spacing([40.0, 4.0])
                 .striped(true)
                 .show(ui, |ui| {
-                    ui.label("Choose a csv file to open");
+                    ui.label("Choose a csv file or workbook to open");
                     if ui.button("Open").clicked() {
-                        let file = FileDialog::new()
-                            .add_filter("csv", &["csv"])
-                            .set_directory("/")
-                            .pick_file();
-
-                        // parse
-                        if let Some(file) = file {
-                            match read_table(file) {
-                                Ok(t) => {
-                                    self.table = Some(t);
-                                }
-                                Err(e) => {
-                                    // Failed to parse the csv file
-                                    println!("Failed to parse the csv file, {:?}", e);
-                                    self.logs.push(LogMessage::new(
-                                        String::from("Failed to parse the csv file"),
-                                        LogLevel::Error,
-                                    ));
-                                }
-                            }
-                        }
+                        self.pick_table_file(ctx, ImportTarget::Table);
                     }
                     ui.end_row();
 
+                    if let Some(task) = &self.loading {
+                        let progress = &task.progress;
+                        ui.label("Loading");
+                        let text = if progress.total() == 0 {
+                            String::from("Reading the workbook")
+                        } else {
+                            format!(
+                                "{:.1}/{:.1} MB read",
+                                progress.done() as f64 / 1e6,
+                                progress.total() as f64 / 1e6
+                            )
+                        };
+                        progress_ui(ui, progress, text, "Stop loading, the current table is kept");
+                        ui.end_row();
+                    }
+
                     ui.label("Table display settings");
                     ui.horizontal(|ui| {
                         ui.checkbox(&mut self.table_settings.striped, "Striped");
@@ -335,12 +988,92 @@ impl eframe::App for TemplateApp {
                         ui.end_row();
 
                         ui.label("Similarity");
-                        ui.add(
-                            egui::DragValue::new(&mut self.edit_distance_settings.similarity)
-                                .clamp_range(0..=100)
-                                .max_decimals(0)
-                                .speed(1.0),
+                        similarity_changed = ui
+                            .add(
+                                egui::Slider::new(
+                                    &mut self.edit_distance_settings.similarity,
+                                    0.0..=100.0,
+                                )
+                                .max_decimals(2)
+                                .suffix("%"),
+                            )
+                            .on_hover_text(format!(
+                                "Regroups as it moves once calculated, down to {}% below the calculated similarity, keeping the review edits",
+                                SCORE_MARGIN
+                            ))
+                            .changed();
+                        ui.end_row();
+
+                        ui.label("Metric");
+                        egui::ComboBox::from_id_source("metric")
+                            .selected_text(self.edit_distance_settings.metric.name())
+                            .show_ui(ui, |ui| {
+                                for metric in Metric::ALL {
+                                    ui.selectable_value(
+                                        &mut self.edit_distance_settings.metric,
+                                        metric,
+                                        metric.name(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Length unit").on_hover_text(
+                            "What lengths and edits are counted in, graphemes score CJK and latin text alike",
                         );
+                        egui::ComboBox::from_id_source("unit")
+                            .selected_text(self.edit_distance_settings.unit.name())
+                            .show_ui(ui, |ui| {
+                                for unit in LengthUnit::ALL {
+                                    ui.selectable_value(
+                                        &mut self.edit_distance_settings.unit,
+                                        unit,
+                                        unit.name(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Clustering");
+                        egui::ComboBox::from_id_source("clustering")
+                            .selected_text(self.edit_distance_settings.clustering.name())
+                            .show_ui(ui, |ui| {
+                                for clustering in Clustering::ALL {
+                                    ui.selectable_value(
+                                        &mut self.edit_distance_settings.clustering,
+                                        clustering,
+                                        clustering.name(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Result");
+                        ui.checkbox(
+                            &mut self.edit_distance_settings.drop_singletons,
+                            "Only groups with duplicates",
+                        );
+                        ui.end_row();
+
+                        ui.label("Normalization")
+                            .on_hover_text("Applied to the compared columns before comparison, previewed as an extra column of the table");
+                        normalization_ui(ui, &mut self.edit_distance_settings.normalization);
+                        ui.end_row();
+
+                        ui.label("Phonetic").on_hover_text(
+                            "Compare pronunciations: Han characters as pinyin, latin words as Soundex or Double Metaphone codes",
+                        );
+                        egui::ComboBox::from_id_source("phonetic")
+                            .selected_text(self.edit_distance_settings.phonetic.name())
+                            .show_ui(ui, |ui| {
+                                for phonetic in Phonetic::ALL {
+                                    ui.selectable_value(
+                                        &mut self.edit_distance_settings.phonetic,
+                                        phonetic,
+                                        phonetic.name(),
+                                    );
+                                }
+                            });
                         ui.end_row();
 
                         ui.label("CaseCensitive");
@@ -350,6 +1083,16 @@ impl eframe::App for TemplateApp {
                         );
                         ui.end_row();
 
+                        ui.label("Threads");
+                        ui.add(
+                            egui::DragValue::new(&mut self.edit_distance_settings.threads)
+                                .clamp_range(0..=256)
+                                .max_decimals(0)
+                                .speed(1.0),
+                        )
+                        .on_hover_text("0 uses one thread per core");
+                        ui.end_row();
+
                         ui.label("Column");
                         egui::ComboBox::from_label("LEN")
                             .selected_text(format!(
@@ -368,38 +1111,63 @@ impl eframe::App for TemplateApp {
                                 }
                             });
                         ui.end_row();
+
+                        ui.label("Match columns")
+                            .on_hover_text("Compare several columns, each with its own metric, weight and minimum similarity. When empty only Column is compared.");
+                        column_matches_ui(ui, &mut self.edit_distance_settings.columns, &t.headers);
+                        ui.end_row();
+
+                        ui.label("Row id").on_hover_text(
+                            "Column identifying the rows, review decisions are saved by its values and honoured by later runs",
+                        );
+                        let id_column = &mut self.edit_distance_settings.id_column;
+                        egui::ComboBox::from_id_source("id_column")
+                            .selected_text(
+                                id_column
+                                    .and_then(|idx| t.headers.get(idx))
+                                    .map_or("None", |h| h.as_str()),
+                            )
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(id_column, None, "None");
+                                for (idx, col_name) in t.headers.iter().enumerate() {
+                                    ui.selectable_value(id_column, Some(idx), col_name);
+                                }
+                            });
+                        ui.end_row();
                     }
                 });
 
-            if let Some(t) = &self.table {
+            let mut cal_similarity = false;
+            if self.table.is_some() {
                 ui.horizontal(|ui| {
                     if ui.button("Cal similarity").clicked() {
-                        let keys: Vec<String> = t
-                            .rows
-                            .iter()
-                            .map(|v| v[self.edit_distance_settings.col_idx].to_owned())
-                            .collect();
-
-                        let ctx = ctx.clone();
-                        let (sender, promise) = Promise::new();
-                        self.result_window.indices = Some(promise);
-                        let similarity = self.edit_distance_settings.similarity;
-                        let case_sensitive = self.edit_distance_settings.case_sensitive;
-
-                        thread::spawn(move || {
-                            let res = group_by_similarity_v2(&keys, similarity, case_sensitive);
-                            sender.send(res);
-                            ctx.request_repaint();
-                        });
+                        cal_similarity = true;
                     }
-                });
-                if let Some(task) = &self.result_window.indices {
-                    if task.ready().is_none() {
-                        ui.label("Calculating...");
-                        ui.spinner();
+                    if ui
+                        .button("Record linkage")
+                        .on_hover_text("Match the rows against the rows of a second table")
+                        .clicked()
+                    {
+                        self.linkage.open = true;
                     }
+                });
+                if let Some(task) = &self.result_window.task {
+                    let progress = &task.progress;
+                    progress_ui(
+                        ui,
+                        progress,
+                        format!("{}/{} pairs checked", progress.done(), progress.total()),
+                        "Stop comparing, the previous result is kept",
+                    );
                 }
             }
+            // Moving the similarity regroups from the cached scores only, a
+            // new comparison waits for the button
+            if cal_similarity {
+                self.cal_similarity(ctx);
+            } else if similarity_changed && self.scores_cover_settings() {
+                self.regroup();
+            }
 
             egui::warn_if_debug_build(ui);
 
@@ -425,78 +1193,344 @@ impl eframe::App for TemplateApp {
                 });
         });
 
-        if let Some(task) = &self.result_window.indices {
-            if let Some(indices) = task.ready() {
-                self.result_window.open = true;
-                let mut window = egui::Window::new("Result")
-                    .resizable(true)
-                    .collapsible(true)
-                    .title_bar(true)
-                    .scroll2([true, true])
-                    .enabled(true);
-                window = window.open(&mut self.result_window.open);
-                window.show(ctx, |ui| {
-                    // Show result table
-                    if self.table.is_none() {
-                        return;
-                    }
-                    let t = self.table.as_ref().unwrap();
+        self.import_dialog_ui(ctx);
+        self.linkage_window_ui(ctx);
 
-                    let text_height = egui::TextStyle::Body.resolve(ui.style()).size;
+        if let Some(task) = self.loading.take() {
+            if task.progress.is_cancelled() {
+                self.logs.push(LogMessage::new(
+                    String::from("Loading cancelled"),
+                    LogLevel::Info,
+                ));
+            } else {
+                match task.try_take() {
+                    Ok((ImportTarget::Table, Ok(t))) => {
+                        self.edit_distance_settings.fit_columns(t.headers.len());
+                        self.table = Some(t);
+                        // The groups index rows of the previous table
+                        self.result_window.review = None;
+                        self.result_window.selected.clear();
+                        self.result_window.scores = None;
+                        self.result_window.exact_keys.clear();
+                        self.result_window.constraints = RowConstraints::default();
+                        if let Some(task) = self.result_window.task.take() {
+                            task.progress.cancel();
+                        }
+                        self.linkage.config.left_column = 0;
+                        self.linkage.reset();
+                    }
+                    Ok((ImportTarget::LinkRight, Ok(t))) => {
+                        self.linkage.right = Some(t);
+                        self.linkage.config.right_column = 0;
+                        self.linkage.reset();
+                    }
+                    Ok((_, Err(e))) => {
+                        // Failed to parse the file
+                        println!("Failed to parse the file, {:?}", e);
+                        self.logs.push(LogMessage::new(
+                            format!("Failed to parse the file: {}", e),
+                            LogLevel::Error,
+                        ));
+                    }
+                    Err(task) => self.loading = Some(task),
+                }
+            }
+        }
 
-                    let mut table = TableBuilder::new(ui)
-                        .striped(self.table_settings.striped)
-                        .resizable(self.table_settings.resizable)
-                        .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
-                        .min_scrolled_height(0.0);
+        if let Some(task) = self.result_window.task.take() {
+            match task.try_take() {
+                Ok(Some((groups, scores, exact_keys))) => {
+                    self.result_window.review = Some(Review::new(groups));
+                    self.result_window.scores = Some(scores);
+                    self.result_window.exact_keys = exact_keys;
+                    self.result_window.selected.clear();
+                    self.result_window.open = true;
+                }
+                Ok(None) => self.logs.push(LogMessage::new(
+                    String::from("Cancelled, the previous result is kept"),
+                    LogLevel::Info,
+                )),
+                Err(task) => self.result_window.task = Some(task),
+            }
+        }
+        if let Some(review) = &mut self.result_window.review {
+            let selected = &mut self.result_window.selected;
+            let mut window = egui::Window::new("Result")
+                .resizable(true)
+                .collapsible(true)
+                .title_bar(true)
+                .scroll2([true, true])
+                .enabled(true);
+            window = window.open(&mut self.result_window.open);
+            window.show(ctx, |ui| {
+                // Show result table
+                if self.table.is_none() {
+                    return;
+                }
+                let t = self.table.as_ref().unwrap();
 
-                    // Index row
-                    table = table.column(Column::auto());
-                    for _ in 0..t.headers.len() {
-                        table = table.column(Column::remainder());
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            selected.len() > 1,
+                            egui::Button::new(format!("Merge selected ({})", selected.len())),
+                        )
+                        .clicked()
+                    {
+                        review.merge(&selected.iter().copied().collect::<Vec<usize>>());
+                        selected.clear();
                     }
-
-                    if let Some(row_nr) = self.table_settings.scroll_to_row.take() {
-                        table = table.scroll_to_row(row_nr, None);
+                    if ui
+                        .add_enabled(review.can_undo(), egui::Button::new("⟲ Undo"))
+                        .clicked()
+                    {
+                        review.undo();
+                        selected.clear();
                     }
+                });
+
+                let text_height = egui::TextStyle::Body.resolve(ui.style()).size;
+
+                let mut table = TableBuilder::new(ui)
+                    .striped(self.table_settings.striped)
+                    .resizable(self.table_settings.resizable)
+                    .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                    .min_scrolled_height(0.0);
+
+                // Group, review actions and Index rows
+                table = table
+                    .column(Column::auto())
+                    .column(Column::auto())
+                    .column(Column::auto());
+                for _ in 0..t.headers.len() {
+                    table = table.column(Column::remainder());
+                }
+
+                if let Some(row_nr) = self.table_settings.scroll_to_row.take() {
+                    table = table.scroll_to_row(row_nr, None);
+                }
 
-                    table
-                        .header(20.0, |mut header| {
-                            // Row Index column
+                let mut action = None;
+                table
+                    .header(20.0, |mut header| {
+                        header.col(|ui| {
+                            ui.strong("Group");
+                        });
+                        header.col(|ui| {
+                            ui.strong("Review");
+                        });
+                        // Row Index column
+                        header.col(|ui| {
+                            ui.strong("Row");
+                        });
+                        for col in &t.headers {
                             header.col(|ui| {
-                                ui.strong("Row");
+                                ui.strong(col);
                             });
-                            for col in &t.headers {
-                                header.col(|ui| {
-                                    ui.strong(col);
-                                });
-                            }
-                        })
-                        .body(|body| {
-                            let row_height = text_height * 1.2;
-                            // flat groups into a vec, append an empty row after every group
-                            let indices = indices.iter().flatten().collect::<Vec<&usize>>();
-                            body.rows(row_height, indices.len(), |idx, mut row| {
-                                row.col(|ui| {
-                                    ui.label(indices[idx].to_string());
-                                });
-                                for col in &t.rows[*indices[idx]] {
-                                    row.col(|ui| {
-                                        ui.label(col);
-                                    });
+                        }
+                    })
+                    .body(|body| {
+                        let row_height = text_height * 1.2;
+                        // flat groups into a vec of (group, position in group, row)
+                        let rows = review
+                            .groups()
+                            .iter()
+                            .enumerate()
+                            .flat_map(|(g, group)| {
+                                group.rows.iter().enumerate().map(move |(i, r)| (g, i, *r))
+                            })
+                            .collect::<Vec<(usize, usize, usize)>>();
+                        body.rows(row_height, rows.len(), |idx, mut row| {
+                            let (g, pos, r) = rows[idx];
+                            let group = &review.groups()[g];
+                            row.col(|ui| {
+                                // Group wide actions on its first row only
+                                if pos > 0 {
+                                    return;
+                                }
+                                let mut checked = selected.contains(&g);
+                                if ui.checkbox(&mut checked, g.to_string()).changed() {
+                                    if checked {
+                                        selected.insert(g);
+                                    } else {
+                                        selected.remove(&g);
+                                    }
                                 }
                             });
+                            row.col(|ui| {
+                                if pos == 0 {
+                                    ui.label(group.status.name());
+                                    if group.rows.len() > 1 {
+                                        if ui
+                                            .small_button("✔")
+                                            .on_hover_text("Confirm group")
+                                            .clicked()
+                                        {
+                                            action = Some(ReviewAction::Confirm(g));
+                                        }
+                                        if ui
+                                            .small_button("🚫")
+                                            .on_hover_text("Not duplicates, split the group")
+                                            .clicked()
+                                        {
+                                            action = Some(ReviewAction::NotDuplicate(g));
+                                        }
+                                    }
+                                }
+                                if group.rows.len() > 1
+                                    && ui
+                                        .small_button("✖")
+                                        .on_hover_text("Remove row from group")
+                                        .clicked()
+                                {
+                                    action = Some(ReviewAction::RemoveRow(g, r));
+                                }
+                            });
+                            row.col(|ui| {
+                                ui.label(r.to_string());
+                            });
+                            for col in &t.rows[r] {
+                                row.col(|ui| {
+                                    ui.label(col);
+                                });
+                            }
                         });
+                    });
+                if let Some(action) = action {
+                    match action {
+                        ReviewAction::Confirm(g) => review.confirm(g),
+                        ReviewAction::RemoveRow(g, r) => review.remove_row(g, r),
+                        ReviewAction::NotDuplicate(g) => review.mark_not_duplicate(g),
+                    }
+                    // Group indices change with the edit
+                    selected.clear();
+                }
 
-                    ui.separator();
-
-                    // Show stats
-                    // How many groups
-                    ui.label(format!("Groups: {}", indices.len()));
-                    if ui.button("Export").clicked() {
-                        let output = FileDialog::new().add_filter("csv", &["csv"]).save_file();
+                ui.separator();
+
+                // Show stats
+                // How many groups
+                let groups = review.row_groups();
+                ui.label(format!("Groups: {}", groups.len()));
+                let stats = GroupStats::new(&groups, t.rows.len());
+                ui.label(format!(
+                    "Duplicate groups: {} ({} rows), unique rows: {}",
+                    stats.duplicate_groups, stats.duplicate_rows, stats.unique_rows
+                ));
+                if self.result_window.exact_keys.len() == t.rows.len() {
+                    let kinds = DuplicateKinds::new(&groups, &self.result_window.exact_keys);
+                    ui.label(format!(
+                        "{} exact and {} fuzzy duplicate rows",
+                        kinds.exact_rows, kinds.fuzzy_rows
+                    ));
+                }
+                let id_column = self
+                    .edit_distance_settings
+                    .id_column
+                    .and_then(|idx| t.headers.get(idx));
+                if ui
+                    .add_enabled(
+                        id_column.is_some() && !review.decisions().is_empty(),
+                        egui::Button::new("Save decisions"),
+                    )
+                    .on_hover_text(
+                        "Keep the review decisions for later runs, keyed by the Row id column",
+                    )
+                    .clicked()
+                {
+                    let id_column = id_column.unwrap();
+                    let path = Constraints::sidecar_path(&t.file);
+                    let saved = if path.exists() {
+                        Constraints::load(&path).map_err(|e| e.to_string())
+                    } else {
+                        Ok(Constraints::new(id_column))
+                    }
+                    .and_then(|mut constraints| {
+                        if &constraints.id_column != id_column {
+                            return Err(format!(
+                                "decisions are keyed by column {:?}, not {:?}",
+                                constraints.id_column, id_column
+                            ));
+                        }
+                        constraints.add(t, review.decisions())?;
+                        constraints.save(&path).map_err(|e| e.to_string())
+                    });
+                    match saved {
+                        Ok(_) => self.logs.push(LogMessage::new(
+                            format!("Saved decisions to {:?}", path),
+                            LogLevel::Info,
+                        )),
+                        Err(e) => self.logs.push(LogMessage::new(
+                            format!("Failed to save decisions to {:?}: {}", path, e),
+                            LogLevel::Error,
+                        )),
+                    }
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Export encoding")
+                        .on_hover_text("UTF-8 with BOM lets Excel recognize UTF-8 files");
+                    encoding_combo(ui, "export_encoding", &mut self.export_encoding);
+                });
+                ui.horizontal(|ui| {
+                    let format = &mut self.export_format;
+                    egui::ComboBox::from_id_source("export_format")
+                        .selected_text(format.name())
+                        .show_ui(ui, |ui| {
+                            for f in ExportFormat::ALL {
+                                if f != ExportFormat::Xlsx {
+                                    ui.selectable_value(format, f, f.name());
+                                }
+                            }
+                        });
+                    if ui
+                        .button("Export")
+                        .on_hover_text(
+                            "In the selected format, its extension is added when missing",
+                        )
+                        .clicked()
+                    {
+                        let format = self.export_format;
+                        let output = FileDialog::new()
+                            .add_filter(format.name(), &[format.extension()])
+                            .save_file()
+                            .map(|f| match f.extension() {
+                                Some(_) => f,
+                                None => f.with_extension(format.extension()),
+                            });
+                        let config = self.edit_distance_settings.grouping_config();
                         match output {
-                            Some(f) => match write_table(&f, t, indices) {
+                            Some(f) => match write_groups(
+                                &f,
+                                format,
+                                t,
+                                &groups,
+                                &config,
+                                self.export_encoding,
+                            ) {
+                                Ok(_) => self.logs.push(LogMessage::new(
+                                    format!("Exported to {:?}", f),
+                                    LogLevel::Info,
+                                )),
+                                Err(e) => self.logs.push(LogMessage::new(
+                                    format!("Failed to export to {:?}: {:?}", f, e),
+                                    LogLevel::Error,
+                                )),
+                            },
+                            None => self.logs.push(LogMessage::new(
+                                String::from("Failed to select output"),
+                                LogLevel::Warning,
+                            )),
+                        }
+                    }
+                    if ui
+                        .button("Export XLSX")
+                        .on_hover_text("Groups told apart by colour, with a summary sheet")
+                        .clicked()
+                    {
+                        let output = FileDialog::new().add_filter("xlsx", &["xlsx"]).save_file();
+                        let config = self.edit_distance_settings.grouping_config();
+                        match output {
+                            Some(f) => match write_xlsx(&f, t, &groups, &config) {
                                 Ok(_) => self.logs.push(LogMessage::new(
                                     format!("Exported to {:?}", f),
                                     LogLevel::Info,
@@ -513,140 +1547,313 @@ impl eframe::App for TemplateApp {
                         }
                     }
                 });
-            }
+
+                let merge_config = &mut self.merge_config;
+                egui::CollapsingHeader::new("Golden records")
+                    .id_source("golden_records")
+                    .show(ui, |ui| {
+                        merge_config_ui(ui, merge_config, &t.headers);
+                    });
+                if ui
+                    .button("Export merged")
+                    .on_hover_text("One surviving row per group, picked by the golden record rules")
+                    .clicked()
+                {
+                    let output = FileDialog::new().add_filter("csv", &["csv"]).save_file();
+                    match output {
+                        Some(f) => match write_merged(
+                            &f,
+                            t,
+                            &groups,
+                            &self.merge_config,
+                            self.export_encoding,
+                        ) {
+                            Ok(rows) => self.logs.push(LogMessage::new(
+                                format!("Exported {} merged rows to {:?}", rows, f),
+                                LogLevel::Info,
+                            )),
+                            Err(e) => self.logs.push(LogMessage::new(
+                                format!("Failed to export to {:?}: {:?}", f, e),
+                                LogLevel::Error,
+                            )),
+                        },
+                        None => self.logs.push(LogMessage::new(
+                            String::from("Failed to select output"),
+                            LogLevel::Warning,
+                        )),
+                    }
+                }
+            });
         }
     }
 }
 
-fn read_table(csv: PathBuf) -> Result<Table, std::io::Error> {
-    let mut rdr = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_path(&csv)?;
-    let headers: Vec<String> = rdr.headers()?.into_iter().map(|b| b.to_owned()).collect();
-    let mut rows: Vec<Vec<String>> = Vec::new();
-    for record in rdr.records() {
-        if let Err(e) = record {
-            return Err(e.into());
+fn column_matches_ui(ui: &mut egui::Ui, columns: &mut Vec<ColumnMatch>, headers: &[String]) {
+    ui.vertical(|ui| {
+        let mut removed = None;
+        for (i, column) in columns.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source(("match_column", i))
+                    .selected_text(headers.get(column.col_idx).map_or("", |h| h.as_str()))
+                    .show_ui(ui, |ui| {
+                        ui.style_mut().wrap = Some(false);
+                        for (idx, col_name) in headers.iter().enumerate() {
+                            ui.selectable_value(&mut column.col_idx, idx, col_name);
+                        }
+                    });
+                egui::ComboBox::from_id_source(("match_metric", i))
+                    .selected_text(column.metric.name())
+                    .show_ui(ui, |ui| {
+                        for metric in Metric::ALL {
+                            ui.selectable_value(&mut column.metric, metric, metric.name());
+                        }
+                    });
+                ui.label("Weight");
+                ui.add(egui::DragValue::new(&mut column.weight).clamp_range(0..=100));
+                ui.label("Min");
+                ui.add(
+                    egui::DragValue::new(&mut column.similarity)
+                        .clamp_range(0.0..=100.0)
+                        .max_decimals(2)
+                        .speed(0.1)
+                        .suffix("%"),
+                );
+                if ui.button("🗑").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            columns.remove(i);
+        }
+        if ui.button("➕ Add column").clicked() {
+            columns.push(ColumnMatch::default());
         }
-        let row: Vec<String> = record.unwrap().into_iter().map(|b| b.to_owned()).collect();
-        rows.push(row);
-    }
-    return Ok(Table {
-        headers,
-        rows,
-        file: csv,
     });
 }
 
-// fn group_by_edit_distance(keys: &Vec<String>, max_step: usize) -> Vec<Vec<usize>> {
-//     let mut groups: Vec<Vec<usize>> = (0..keys.len()).map(|i| vec![i]).collect();
-//     for group in groups.iter_mut() {
-//         for i in 0..keys.len() {
-//             if group.contains(&i) {
-//                 continue;
-//             }
-//             if levenshtein_distance(&keys[group[0]], &keys[i]) <= max_step {
-//                 group.push(i);
-//             }
-//         }
-//     }
-//     return groups;
-// }
-
-fn cal_similarity(left: &str, right: &str) -> usize {
-    let lev_dis = levenshtein_distance(left, right);
-    let max_len = std::cmp::max(left.len(), right.len());
-
-    // Meaning that both strings are empty
-    if max_len == 0 {
-        return 100;
-    }
-    return (max_len - lev_dis) * 100 / max_len;
-}
+/// The settings of a csv dialect as grid rows, returns whether one changed.
+fn dialect_ui(ui: &mut egui::Ui, dialect: &mut CsvDialect) -> bool {
+    let before = *dialect;
+
+    ui.label("Encoding");
+    encoding_combo(ui, "import_encoding", &mut dialect.encoding);
+    ui.end_row();
+
+    ui.label("Delimiter");
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source("delimiter")
+            .selected_text(delimiter_name(dialect.delimiter))
+            .show_ui(ui, |ui| {
+                for (delimiter, name) in DELIMITERS {
+                    ui.selectable_value(&mut dialect.delimiter, delimiter, name);
+                }
+            });
+        byte_edit(ui, &mut dialect.delimiter);
+    });
+    ui.end_row();
+
+    ui.label("Quote");
+    byte_edit(ui, &mut dialect.quote);
+    ui.end_row();
+
+    ui.label("Escape")
+        .on_hover_text("Escapes quotes inside quoted values, otherwise quotes are doubled");
+    optional_byte_edit(ui, &mut dialect.escape, b'\\');
+    ui.end_row();
 
-fn cal_similarity_case_insentive(left: &str, right: &str) -> usize {
-    let left = left.to_lowercase();
-    let right = right.to_lowercase();
-    return cal_similarity(&left, &right);
+    ui.label("Comment")
+        .on_hover_text("Lines starting with it are skipped");
+    optional_byte_edit(ui, &mut dialect.comment, b'#');
+    ui.end_row();
+
+    ui.label("Header row");
+    ui.checkbox(&mut dialect.has_headers, "First row holds the column names");
+    ui.end_row();
+
+    ui.label("Flexible");
+    ui.checkbox(&mut dialect.flexible, "Rows may have different lengths");
+    ui.end_row();
+
+    *dialect != before
 }
 
-#[allow(dead_code)]
-fn group_by_similarity(
-    keys: &Vec<String>,
-    similarity: usize,
-    case_sensitive: bool,
-) -> Vec<Vec<usize>> {
-    let mut groups: Vec<Vec<usize>> = (0..keys.len()).map(|i| vec![i]).collect();
-    for group in groups.iter_mut() {
-        for i in 0..keys.len() {
-            if group.contains(&i) {
-                continue;
+/// The sheet and header row of a workbook as grid rows, returns whether one
+/// changed.
+fn sheet_options_ui(ui: &mut egui::Ui, sheets: &[String], options: &mut SheetOptions) -> bool {
+    let before = options.clone();
+
+    ui.label("Sheet");
+    egui::ComboBox::from_id_source("sheet")
+        .selected_text(options.sheet.as_str())
+        .show_ui(ui, |ui| {
+            for sheet in sheets {
+                ui.selectable_value(&mut options.sheet, sheet.clone(), sheet);
             }
-            let cal = if case_sensitive {
-                cal_similarity
-            } else {
-                cal_similarity_case_insentive
-            };
-
-            if cal(&keys[group[0]], &keys[i]) >= similarity {
-                group.push(i);
+        });
+    ui.end_row();
+
+    ui.label("Header row")
+        .on_hover_text("Row holding the column names, as numbered by the spreadsheet. The rows above it are skipped.");
+    ui.horizontal(|ui| {
+        let mut has_headers = options.header_row.is_some();
+        if ui.checkbox(&mut has_headers, "").changed() {
+            options.header_row = has_headers.then_some(0);
+        }
+        if let Some(row) = &mut options.header_row {
+            let mut number = *row + 1;
+            if ui
+                .add(egui::DragValue::new(&mut number).clamp_range(1..=1_048_576))
+                .changed()
+            {
+                *row = number - 1;
             }
         }
+    });
+    ui.end_row();
+
+    *options != before
+}
+
+/// A progress bar of `progress`, labelled `text` and the time left, with a
+/// button cancelling it.
+fn progress_ui(ui: &mut egui::Ui, progress: &Progress, mut text: String, cancel_hover: &str) {
+    if let Some(eta) = progress.eta() {
+        text += &format!(", {}s left", eta.as_secs());
     }
-    return groups;
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::ProgressBar::new(progress.fraction())
+                .text(text)
+                .desired_width(300.0)
+                .animate(progress.total() == 0),
+        );
+        if ui.button("Cancel").on_hover_text(cancel_hover).clicked() {
+            progress.cancel();
+        }
+    });
+    // The workers only repaint once done
+    ui.ctx().request_repaint_after(Duration::from_millis(100));
 }
 
-fn group_by_similarity_v2(
-    keys: &Vec<String>,
-    similarity: usize,
-    case_sensitive: bool,
-) -> Vec<Vec<usize>> {
-    let mut groups: Vec<Vec<usize>> = (0..keys.len()).map(|i| vec![i]).collect();
-    let mut visited: Vec<bool> = vec![false; keys.len()];
-    for group in groups.iter_mut() {
-        for i in 0..keys.len() {
-            if group.contains(&i) || visited[i] {
-                continue;
+/// A combo box picking one of `headers` by index, returning whether the pick
+/// changed.
+fn column_combo(
+    ui: &mut egui::Ui,
+    id_source: &str,
+    col_idx: &mut usize,
+    headers: &[String],
+) -> bool {
+    let mut changed = false;
+    egui::ComboBox::from_id_source(id_source)
+        .selected_text(headers.get(*col_idx).map_or("", |h| h.as_str()))
+        .show_ui(ui, |ui| {
+            for (idx, header) in headers.iter().enumerate() {
+                changed |= ui.selectable_value(col_idx, idx, header).changed();
             }
-            let cal = if case_sensitive {
-                cal_similarity
-            } else {
-                cal_similarity_case_insentive
-            };
+        });
+    changed
+}
 
-            if cal(&keys[group[0]], &keys[i]) >= similarity {
-                group.push(i);
-                visited[i] = true;
+fn encoding_combo(ui: &mut egui::Ui, id_source: &str, encoding: &mut TextEncoding) {
+    egui::ComboBox::from_id_source(id_source)
+        .selected_text(encoding.name())
+        .show_ui(ui, |ui| {
+            for e in TextEncoding::ALL {
+                ui.selectable_value(encoding, e, e.name());
             }
+        });
+}
+
+/// Edit a single ASCII character by typing another one.
+fn byte_edit(ui: &mut egui::Ui, byte: &mut u8) {
+    let mut text = (*byte as char).to_string();
+    if ui
+        .add(egui::TextEdit::singleline(&mut text).desired_width(16.0))
+        .changed()
+    {
+        if let Some(c) = text.chars().find(|&c| c.is_ascii() && c != *byte as char) {
+            *byte = c as u8;
         }
     }
-    return groups;
-}
-
-fn write_table(
-    csv: &PathBuf,
-    table: &Table,
-    groups: &Vec<Vec<usize>>,
-) -> Result<(), std::io::Error> {
-    let mut wtr = csv::WriterBuilder::new().has_headers(true).from_path(csv)?;
-    // Add index header to original headers
-    let headers: Vec<String> = vec!["Index".to_string()]
-        .into_iter()
-        .chain(table.headers.iter().cloned())
-        .collect();
-    let cols = headers.len();
-    wtr.write_record(headers)?;
-    for group in groups {
-        for r_idx in group {
-            let mut row = vec![r_idx.to_string()];
-            row.extend(table.rows[*r_idx].iter().cloned());
-            wtr.write_record(row)?;
-        }
-        // Write a empty row
-        wtr.write_record([""].repeat(cols))?;
-    }
-    wtr.flush()?;
-    return Ok(());
+}
+
+/// Edit an optional ASCII character, `default` when it gets enabled.
+fn optional_byte_edit(ui: &mut egui::Ui, byte: &mut Option<u8>, default: u8) {
+    ui.horizontal(|ui| {
+        let mut enabled = byte.is_some();
+        if ui.checkbox(&mut enabled, "").changed() {
+            *byte = enabled.then_some(default);
+        }
+        if let Some(byte) = byte {
+            byte_edit(ui, byte);
+        }
+    });
+}
+
+fn merge_config_ui(ui: &mut egui::Ui, config: &mut MergeConfig, headers: &[String]) {
+    config.rules.resize(headers.len(), Survivorship::default());
+    egui::Grid::new("merge_config")
+        .num_columns(2)
+        .show(ui, |ui| {
+            for (col_idx, header) in headers.iter().enumerate() {
+                ui.label(header);
+                egui::ComboBox::from_id_source(("survivorship", col_idx))
+                    .selected_text(config.rules[col_idx].name())
+                    .show_ui(ui, |ui| {
+                        for rule in Survivorship::ALL {
+                            ui.selectable_value(&mut config.rules[col_idx], rule, rule.name());
+                        }
+                    });
+                ui.end_row();
+            }
+
+            ui.label("Date column")
+                .on_hover_text("Dates the rows for the Newest rule, year first like 2023-01-31");
+            egui::ComboBox::from_id_source("date_column")
+                .selected_text(
+                    config
+                        .date_column
+                        .and_then(|idx| headers.get(idx))
+                        .map_or("None", |h| h.as_str()),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut config.date_column, None, "None");
+                    for (idx, col_name) in headers.iter().enumerate() {
+                        ui.selectable_value(&mut config.date_column, Some(idx), col_name);
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Separator")
+                .on_hover_text("Between the values of the Concatenate unique rule");
+            ui.text_edit_singleline(&mut config.separator);
+            ui.end_row();
+        });
+}
+
+fn normalization_ui(ui: &mut egui::Ui, normalization: &mut Normalization) {
+    ui.vertical(|ui| {
+        ui.checkbox(&mut normalization.nfkc, "Unicode NFKC");
+        ui.checkbox(
+            &mut normalization.full_width_to_half_width,
+            "Full-width to half-width",
+        );
+        ui.checkbox(&mut normalization.strip_accents, "Strip accents");
+        ui.checkbox(&mut normalization.remove_punctuation, "Remove punctuation");
+        ui.checkbox(
+            &mut normalization.collapse_whitespace,
+            "Collapse whitespace",
+        );
+        ui.label("Stop words, one per line");
+        let mut stop_words = normalization.stop_words.join("\n");
+        if ui
+            .add(egui::TextEdit::multiline(&mut stop_words).desired_rows(3))
+            .changed()
+        {
+            normalization.stop_words = stop_words.split('\n').map(String::from).collect();
+        }
+    });
 }
 
 fn setup_custom_fonts(ctx: &egui::Context) {
@@ -0,0 +1,492 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Headless version of the "Cal similarity" button, so grouping can be scripted.
+//!
+//! ```text
+//! similar_row_cli --input in.csv --output out.csv --column name [--similarity 90] [--case-insensitive]
+//! similar_row_cli --input in.csv --output out.csv --column name:jaro-winkler:2:80 --column city --similarity 85
+//! similar_row_cli --input in.csv --output golden.csv --column name --merge --rule phone:most-frequent --date-column updated
+//! similar_row_cli --input in.csv --output groups.xlsx --column name --similarity 90
+//! similar_row_cli --input in.csv --output groups.parquet --column name --similarity 90
+//! ```
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use similar_row::grouping::{
+    exact_keys, group, read_table_with, ColumnMatch, DuplicateKinds, GroupStats, GroupingConfig,
+    Table,
+};
+use similar_row::{
+    is_spreadsheet, read_sheet, write_groups, write_merged, Clustering, Constraints, CsvDialect,
+    ExportFormat, LengthUnit, MergeConfig, Metric, Normalization, Phonetic, SheetOptions,
+    Survivorship, TextEncoding,
+};
+
+const USAGE: &str = "\
+Usage: similar_row_cli --input <CSV> --output <CSV> --column <NAME|INDEX> [OPTIONS]
+
+Options:
+  -i, --input <FILE>        CSV file to read, its dialect is guessed unless given below,
+                            or an xlsx, xlsm, xlsb, xls or ods workbook
+  -o, --output <CSV>        Where to write the grouped rows, in the format of its extension:
+                            csv, xlsx (a colour per group and a summary sheet), json (an
+                            array of groups), jsonl (a row per line) or parquet [default: csv]
+      --sheet <NAME>        Sheet of the workbook to read [default: the first one]
+      --header-row <N>      Row of the workbook holding the column names, as numbered by
+                            the spreadsheet, 0 when there is none [default: 1]
+      --encoding <NAME>     Text encoding of the input: utf-8, gb18030, gbk, big5, shift_jis,
+                            euc-kr, windows-1250, windows-1251 or windows-1252
+      --output-encoding <NAME>
+                            Text encoding of the output, the above or utf-8-with-bom for
+                            Excel [default: utf-8]
+      --delimiter <CHAR>    Field delimiter, a single character or tab
+      --quote <CHAR>        Quote character
+      --escape <CHAR>       Escape of quotes inside quoted fields, instead of doubled quotes
+      --headers             The first row holds the column names
+      --no-headers          There is no header row, columns are named \"Column 1\"...
+      --comment <CHAR>      Skip lines starting with this character
+      --flexible            Allow rows of different lengths
+      --no-sniff            Do not guess the dialect, default to comma separated with headers
+  -c, --column <NAME|INDEX>[:METRIC[:WEIGHT[:SIMILARITY]]]
+                            Column to compare, by header name or zero based index.
+                            Repeat to compare several columns, optionally with their own
+                            metric, weight [default: 1] and minimum similarity [default: 0]
+  -s, --similarity <0-100>  Minimum similarity in percent for two rows to be grouped,
+                            fractions allowed [default: 100]
+  -m, --metric <METRIC>     levenshtein, damerau-levenshtein, jaro-winkler, token-set-ratio
+                            or bigram-jaccard [default: levenshtein]
+  -u, --unit <UNIT>         What lengths and edits are counted in: graphemes, chars or bytes
+                            [default: graphemes]
+      --clustering <MODE>   greedy, connected-components or complete-linkage [default: greedy]
+      --case-insensitive    Ignore case when comparing
+      --drop-singletons     Only export groups with two or more rows
+      --nfkc                Apply Unicode compatibility normalization (NFKC)
+      --full-width          Turn full-width letters, digits and spaces into half-width
+      --strip-accents       Remove accents and other diacritics
+      --remove-punctuation  Replace punctuation by spaces
+      --stop-words <WORDS>  Comma separated words to remove, e.g. Ltd,Inc,有限公司
+      --collapse-whitespace Trim and collapse runs of whitespace
+      --normalize           All of the normalization steps above but stop words
+      --phonetic <MODE>     Compare phonetic keys: off, pinyin, soundex or double-metaphone.
+                            Han characters are turned into pinyin first [default: off]
+      --constraints <JSON>  Review decisions to honour, as saved from the result window
+                            [default: <INPUT>.constraints.json next to the input, if any]
+      --merge               Export one surviving row per group instead of the grouped rows.
+                            Every row is merged, --drop-singletons only affects grouped output
+      --rule <NAME|INDEX>:<RULE>
+                            Survivorship rule of a column when merging: most-frequent, longest,
+                            first-non-empty, newest or concatenate-unique. Repeat for several
+                            columns, the others use --default-rule
+      --default-rule <RULE> Survivorship rule of the other columns [default: first-non-empty]
+      --date-column <NAME|INDEX>
+                            Column dating the rows for the newest rule, year first dates
+      --separator <TEXT>    Between the values of concatenate-unique [default: \"; \"]
+  -t, --threads <N>         Worker threads, 0 for one per core [default: 0]
+  -h, --help                Print this help";
+
+struct Args {
+    input: PathBuf,
+    output: PathBuf,
+    sheet: SheetOptions,
+    sniff: bool,
+    encoding: Option<TextEncoding>,
+    output_encoding: TextEncoding,
+    delimiter: Option<u8>,
+    quote: Option<u8>,
+    escape: Option<u8>,
+    has_headers: Option<bool>,
+    comment: Option<u8>,
+    flexible: bool,
+    columns: Vec<String>,
+    similarity: f64,
+    case_sensitive: bool,
+    metric: Metric,
+    unit: LengthUnit,
+    clustering: Clustering,
+    drop_singletons: bool,
+    normalization: Normalization,
+    phonetic: Phonetic,
+    constraints: Option<PathBuf>,
+    merge: bool,
+    rules: Vec<String>,
+    default_rule: Survivorship,
+    date_column: Option<String>,
+    separator: String,
+    threads: usize,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut sheet = SheetOptions::default();
+    let mut sniff = true;
+    let mut encoding = None;
+    let mut output_encoding = TextEncoding::Utf8;
+    let mut delimiter = None;
+    let mut quote = None;
+    let mut escape = None;
+    let mut has_headers = None;
+    let mut comment = None;
+    let mut flexible = false;
+    let mut columns = Vec::new();
+    let mut similarity = 100.0;
+    let mut case_sensitive = true;
+    let mut metric = Metric::Levenshtein;
+    let mut unit = LengthUnit::Graphemes;
+    let mut clustering = Clustering::Greedy;
+    let mut drop_singletons = false;
+    let mut normalization = Normalization::default();
+    let mut phonetic = Phonetic::Off;
+    let mut constraints = None;
+    let mut merge = false;
+    let mut rules = Vec::new();
+    let mut default_rule = Survivorship::FirstNonEmpty;
+    let mut date_column = None;
+    let mut separator = MergeConfig::default().separator;
+    let mut threads = 0;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("Missing value for {}", name));
+        match arg.as_str() {
+            "-i" | "--input" => input = Some(PathBuf::from(value(&arg)?)),
+            "-o" | "--output" => output = Some(PathBuf::from(value(&arg)?)),
+            "--sheet" => sheet.sheet = value(&arg)?,
+            "--header-row" => {
+                let row = value(&arg)?
+                    .parse::<usize>()
+                    .map_err(|_| "Header row must be a non negative integer")?;
+                sheet.header_row = row.checked_sub(1);
+            }
+            "--encoding" => encoding = Some(parse_encoding(&value(&arg)?)?),
+            "--output-encoding" => output_encoding = parse_encoding(&value(&arg)?)?,
+            "--delimiter" => delimiter = Some(parse_char(&arg, &value(&arg)?)?),
+            "--quote" => quote = Some(parse_char(&arg, &value(&arg)?)?),
+            "--escape" => escape = Some(parse_char(&arg, &value(&arg)?)?),
+            "--headers" => has_headers = Some(true),
+            "--no-headers" => has_headers = Some(false),
+            "--comment" => comment = Some(parse_char(&arg, &value(&arg)?)?),
+            "--flexible" => flexible = true,
+            "--no-sniff" => sniff = false,
+            "-c" | "--column" => columns.push(value(&arg)?),
+            "-s" | "--similarity" => {
+                similarity = value(&arg)?
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|s| (0.0..=100.0).contains(s))
+                    .ok_or("Similarity must be a number between 0 and 100")?;
+            }
+            "-m" | "--metric" => metric = parse_metric(&value(&arg)?)?,
+            "-u" | "--unit" => {
+                let name = value(&arg)?.to_lowercase();
+                unit = LengthUnit::ALL
+                    .into_iter()
+                    .find(|u| u.name().to_lowercase() == name)
+                    .ok_or(format!("Unknown unit {:?}", name))?;
+            }
+            "--clustering" => {
+                let name = value(&arg)?.to_lowercase();
+                clustering = Clustering::ALL
+                    .into_iter()
+                    .find(|c| c.name().to_lowercase().replace(' ', "-") == name)
+                    .ok_or(format!("Unknown clustering {:?}", name))?;
+            }
+            "--case-insensitive" => case_sensitive = false,
+            "--drop-singletons" => drop_singletons = true,
+            "--nfkc" => normalization.nfkc = true,
+            "--full-width" => normalization.full_width_to_half_width = true,
+            "--strip-accents" => normalization.strip_accents = true,
+            "--remove-punctuation" => normalization.remove_punctuation = true,
+            "--stop-words" => {
+                normalization
+                    .stop_words
+                    .extend(value(&arg)?.split(',').map(String::from));
+            }
+            "--collapse-whitespace" => normalization.collapse_whitespace = true,
+            "--normalize" => {
+                normalization = Normalization {
+                    nfkc: true,
+                    full_width_to_half_width: true,
+                    strip_accents: true,
+                    remove_punctuation: true,
+                    collapse_whitespace: true,
+                    ..normalization
+                };
+            }
+            "--phonetic" => {
+                let name = value(&arg)?.to_lowercase();
+                phonetic = Phonetic::ALL
+                    .into_iter()
+                    .find(|p| p.name().to_lowercase().replace(' ', "-") == name)
+                    .ok_or(format!("Unknown phonetic mode {:?}", name))?;
+            }
+            "--constraints" => constraints = Some(PathBuf::from(value(&arg)?)),
+            "--merge" => merge = true,
+            "--rule" => rules.push(value(&arg)?),
+            "--default-rule" => default_rule = parse_rule(&value(&arg)?)?,
+            "--date-column" => date_column = Some(value(&arg)?),
+            "--separator" => separator = value(&arg)?,
+            "-t" | "--threads" => {
+                threads = value(&arg)?
+                    .parse::<usize>()
+                    .map_err(|_| "Threads must be a non negative integer")?;
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(format!("Unknown argument {:?}", arg)),
+        }
+    }
+
+    if columns.is_empty() {
+        return Err(String::from("Missing --column"));
+    }
+    Ok(Args {
+        input: input.ok_or("Missing --input")?,
+        output: output.ok_or("Missing --output")?,
+        sheet,
+        sniff,
+        encoding,
+        output_encoding,
+        delimiter,
+        quote,
+        escape,
+        has_headers,
+        comment,
+        flexible,
+        columns,
+        similarity,
+        case_sensitive,
+        metric,
+        unit,
+        clustering,
+        drop_singletons,
+        normalization,
+        phonetic,
+        constraints,
+        merge,
+        rules,
+        default_rule,
+        date_column,
+        separator,
+        threads,
+    })
+}
+
+fn parse_encoding(name: &str) -> Result<TextEncoding, String> {
+    let name = name.to_lowercase();
+    TextEncoding::ALL
+        .into_iter()
+        .find(|e| e.name().to_lowercase().replace(' ', "-") == name)
+        .ok_or(format!("Unknown encoding {:?}", name))
+}
+
+/// Parse a single ASCII character, or `tab`.
+fn parse_char(arg: &str, value: &str) -> Result<u8, String> {
+    match value {
+        "tab" | "\\t" => Ok(b'\t'),
+        _ if value.len() == 1 && value.is_ascii() => Ok(value.as_bytes()[0]),
+        _ => Err(format!(
+            "{} must be a single ASCII character, got {:?}",
+            arg, value
+        )),
+    }
+}
+
+/// The guessed dialect of the input, overridden by the given settings.
+fn dialect(args: &Args) -> Result<CsvDialect, String> {
+    let mut dialect = if args.sniff {
+        CsvDialect::sniff_file(&args.input)
+            .map_err(|e| format!("Failed to read {:?}: {}", args.input, e))?
+    } else {
+        CsvDialect::default()
+    };
+    dialect.encoding = args.encoding.unwrap_or(dialect.encoding);
+    dialect.delimiter = args.delimiter.unwrap_or(dialect.delimiter);
+    dialect.quote = args.quote.unwrap_or(dialect.quote);
+    dialect.escape = args.escape.or(dialect.escape);
+    dialect.has_headers = args.has_headers.unwrap_or(dialect.has_headers);
+    dialect.comment = args.comment.or(dialect.comment);
+    dialect.flexible |= args.flexible;
+    Ok(dialect)
+}
+
+fn parse_metric(name: &str) -> Result<Metric, String> {
+    let name = name.to_lowercase();
+    Metric::ALL
+        .into_iter()
+        .find(|m| m.name().to_lowercase().replace(' ', "-") == name)
+        .ok_or(format!("Unknown metric {:?}", name))
+}
+
+fn parse_rule(name: &str) -> Result<Survivorship, String> {
+    let name = name.to_lowercase();
+    Survivorship::ALL
+        .into_iter()
+        .find(|r| r.name().to_lowercase().replace(' ', "-") == name)
+        .ok_or(format!("Unknown survivorship rule {:?}", name))
+}
+
+/// The merge settings from the `--rule NAME:RULE` and related arguments.
+fn merge_config(table: &Table, args: &Args) -> Result<MergeConfig, String> {
+    let column_index = |name: &str| {
+        table
+            .column_index(name)
+            .ok_or(format!("No column {:?} in {:?}", name, table.file))
+    };
+    let mut config = MergeConfig {
+        rules: vec![args.default_rule; table.headers.len()],
+        date_column: args.date_column.as_deref().map(column_index).transpose()?,
+        separator: args.separator.clone(),
+    };
+    for spec in &args.rules {
+        // Header names may contain colons themselves, the rule never does
+        let (name, rule) = spec
+            .rsplit_once(':')
+            .ok_or(format!("Expected NAME:RULE, got {:?}", spec))?;
+        config.rules[column_index(name)?] = parse_rule(rule)?;
+    }
+    if config.date_column.is_none() && config.rules.contains(&Survivorship::Newest) {
+        return Err(String::from("The newest rule needs --date-column"));
+    }
+    Ok(config)
+}
+
+/// Parse a `NAME[:METRIC[:WEIGHT[:SIMILARITY]]]` column argument.
+fn parse_column(table: &Table, spec: &str, metric: Metric) -> Result<ColumnMatch, String> {
+    let mut column = ColumnMatch {
+        metric,
+        ..Default::default()
+    };
+    // Header names may contain colons themselves
+    if let Some(col_idx) = table.column_index(spec) {
+        column.col_idx = col_idx;
+        return Ok(column);
+    }
+
+    let mut parts = spec.split(':');
+    let name = parts.next().unwrap_or_default();
+    column.col_idx = table
+        .column_index(name)
+        .ok_or(format!("No column {:?} in {:?}", name, table.file))?;
+    if let Some(metric) = parts.next() {
+        column.metric = parse_metric(metric)?;
+    }
+    if let Some(weight) = parts.next() {
+        column.weight = weight
+            .parse()
+            .map_err(|_| format!("Invalid weight {:?} for column {:?}", weight, name))?;
+    }
+    if let Some(similarity) = parts.next() {
+        column.similarity = similarity
+            .parse()
+            .ok()
+            .filter(|s| (0.0..=100.0).contains(s))
+            .ok_or(format!(
+                "Invalid similarity {:?} for column {:?}",
+                similarity, name
+            ))?;
+    }
+    Ok(column)
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let format = ExportFormat::from_path(&args.output);
+    if args.merge && format != ExportFormat::Csv {
+        return Err(String::from("--merge only exports csv files"));
+    }
+    let table = if is_spreadsheet(&args.input) {
+        read_sheet(args.input.clone(), &args.sheet)
+    } else {
+        read_table_with(args.input.clone(), &dialect(&args)?)
+    }
+    .map_err(|e| format!("Failed to parse the csv file {:?}: {}", args.input, e))?;
+    let columns = args
+        .columns
+        .iter()
+        .map(|spec| parse_column(&table, spec, args.metric))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Checked before grouping, which may take a while
+    let merge = if args.merge {
+        Some(merge_config(&table, &args)?)
+    } else {
+        None
+    };
+
+    let mut config = GroupingConfig {
+        col_idx: columns[0].col_idx,
+        similarity: args.similarity,
+        case_sensitive: args.case_sensitive,
+        metric: columns[0].metric,
+        unit: args.unit,
+        threads: args.threads,
+        columns: Vec::new(),
+        clustering: args.clustering,
+        drop_singletons: args.drop_singletons,
+        normalization: args.normalization,
+        phonetic: args.phonetic,
+        constraints: Default::default(),
+    };
+    // A single column only needs its own settings for its own threshold
+    if columns.len() > 1 || columns[0].similarity > 0.0 {
+        config.columns = columns;
+    }
+    // The sidecar file is optional, an explicitly given file is not
+    let constraints_file = args
+        .constraints
+        .clone()
+        .unwrap_or_else(|| Constraints::sidecar_path(&args.input));
+    if args.constraints.is_some() || constraints_file.exists() {
+        let constraints = Constraints::load(&constraints_file)
+            .map_err(|e| format!("Failed to load {:?}: {}", constraints_file, e))?;
+        config.constraints = constraints.resolve(&table)?;
+    }
+    let groups = group(&table, &config);
+
+    if let Some(merge) = &merge {
+        write_merged(&args.output, &table, &groups, merge, args.output_encoding).map(|_| ())
+    } else {
+        write_groups(
+            &args.output,
+            format,
+            &table,
+            &groups,
+            &config,
+            args.output_encoding,
+        )
+    }
+    .map_err(|e| format!("Failed to export to {:?}: {}", args.output, e))?;
+    let stats = GroupStats::new(&groups, table.rows.len());
+    let kinds = DuplicateKinds::new(&groups, &exact_keys(&table, &config));
+    println!(
+        "Grouped {} rows into {} groups ({} duplicate groups of {} rows, {} exact and {} fuzzy, {} unique rows), exported to {:?}",
+        table.rows.len(),
+        groups.len(),
+        stats.duplicate_groups,
+        stats.duplicate_rows,
+        kinds.exact_rows,
+        kinds.fuzzy_rows,
+        stats.unique_rows,
+        args.output
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(args) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
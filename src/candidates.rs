@@ -0,0 +1,404 @@
+//! Candidate generation for the pairwise similarity step.
+//!
+//! Comparing every key with every other key is quadratic in full Levenshtein
+//! computations. Instead the keys are sorted by length and, for each key, only
+//! the keys that can still reach the similarity threshold are looked at:
+//!
+//! * length filter: an edit changes the length of a key by at most one unit,
+//!   so keys that are too long can never be similar enough;
+//! * count filter: `d` edits destroy at most `2 * d` bigrams, so similar keys
+//!   have to share a minimum number of bigrams of units, found through an
+//!   inverted index.
+//!
+//! Both filters only drop pairs that would fail the threshold anyway, so the
+//! result is identical to comparing all pairs. They only hold for the
+//! Levenshtein metric; without a Levenshtein column every pair is compared.
+//!
+//! When linking two tables only the pairs across them are candidates, see
+//! [`Candidates::across`].
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::edit_distance::{max_distance, LengthUnit};
+use crate::progress::Progress;
+
+/// Length of the grams used by the count filter, in units.
+const Q: usize = 2;
+
+/// Number of sorted positions a worker takes at once.
+const CHUNK: usize = 64;
+
+pub(crate) struct Candidates {
+    /// Whether the filters apply, every pair is a candidate otherwise.
+    filtered: bool,
+    similarity: f64,
+    /// Key indices sorted by length; everything below is by sorted position.
+    order: Vec<usize>,
+    /// Length of every key in units.
+    lengths: Vec<usize>,
+    /// Distinct bigrams of every key with their number of occurrences.
+    grams: Vec<Vec<(u64, usize)>>,
+    /// Sorted positions (and occurrences) of the keys containing a bigram.
+    postings: HashMap<u64, Vec<(usize, usize)>>,
+    /// The number of left keys when only pairs of a left and a right key are
+    /// candidates.
+    split: Option<usize>,
+    /// With `split`, the number of right keys before every sorted position.
+    rights_before: Vec<usize>,
+}
+
+impl Candidates {
+    /// Every pair of `len` keys.
+    pub(crate) fn all(len: usize) -> Self {
+        Self {
+            filtered: false,
+            similarity: 0.0,
+            order: (0..len).collect(),
+            lengths: Vec::new(),
+            grams: Vec::new(),
+            postings: HashMap::new(),
+            split: None,
+            rights_before: Vec::new(),
+        }
+    }
+
+    /// The pairs of `keys` that may reach `similarity` by [`crate::edit_distance::Levenshtein`]
+    /// counting in `unit`.
+    pub(crate) fn new(keys: &[String], similarity: f64, unit: LengthUnit) -> Self {
+        let units: Vec<Vec<&[u8]>> = keys.iter().map(|k| unit.split(k)).collect();
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by_key(|&i| units[i].len());
+
+        let mut ids: HashMap<&[u8], u64> = HashMap::new();
+        let mut lengths = Vec::with_capacity(keys.len());
+        let mut grams = Vec::with_capacity(keys.len());
+        let mut postings: HashMap<u64, Vec<(usize, usize)>> = HashMap::new();
+        for (pos, &i) in order.iter().enumerate() {
+            let mut seq = Vec::with_capacity(units[i].len());
+            for &u in &units[i] {
+                let next_id = ids.len() as u64;
+                seq.push(*ids.entry(u).or_insert(next_id));
+            }
+            let mut key_grams: Vec<u64> = seq.windows(Q).map(|w| (w[0] << 32) | w[1]).collect();
+            key_grams.sort_unstable();
+            let mut counted: Vec<(u64, usize)> = Vec::new();
+            for gram in key_grams {
+                match counted.last_mut() {
+                    Some((last, count)) if *last == gram => *count += 1,
+                    _ => counted.push((gram, 1)),
+                }
+            }
+            for &(gram, count) in &counted {
+                postings.entry(gram).or_default().push((pos, count));
+            }
+            lengths.push(seq.len());
+            grams.push(counted);
+        }
+
+        Self {
+            filtered: true,
+            similarity,
+            order,
+            lengths,
+            grams,
+            postings,
+            split: None,
+            rights_before: Vec::new(),
+        }
+    }
+
+    /// Only keep the pairs of a left key, one of the first `left` keys, with a
+    /// right key, one of the others: the keys of two tables one after the
+    /// other, matched against each other.
+    pub(crate) fn across(mut self, left: usize) -> Self {
+        self.rights_before = std::iter::once(0)
+            .chain(self.order.iter().scan(0, |rights, &i| {
+                *rights += usize::from(i >= left);
+                Some(*rights)
+            }))
+            .collect();
+        self.split = Some(left);
+        self
+    }
+
+    /// Whether the keys at sorted positions `pos` and `other` may be paired,
+    /// being on different sides when there are two.
+    fn crosses(&self, pos: usize, other: usize) -> bool {
+        match self.split {
+            Some(split) => (self.order[pos] < split) != (self.order[other] < split),
+            None => true,
+        }
+    }
+
+    /// The most edits two keys may differ by to be similar, when the longer
+    /// one is `max_len` units long, `None` when they cannot be similar.
+    fn max_distance(&self, max_len: usize) -> Option<usize> {
+        max_distance(max_len, self.similarity)
+    }
+
+    /// The longest key length that may be similar to the key at sorted
+    /// position `pos`, and the sorted position after the last key of that
+    /// length.
+    fn length_window(&self, pos: usize) -> (usize, usize) {
+        // A key of length `l` is `l - len` edits away at least, which has to
+        // stay within `l * (100 - similarity) / 100`
+        let len = self.lengths[pos];
+        let max_len = *self.lengths.last().unwrap_or(&0);
+        let upper = if self.similarity > 0.0 {
+            max_len.min((100.0 * len as f64 / self.similarity).ceil() as usize)
+        } else {
+            max_len
+        };
+        let end = pos + 1 + self.lengths[pos + 1..].partition_point(|&l| l <= upper);
+        (upper, end)
+    }
+
+    /// The number of keys after sorted position `pos` passing the length
+    /// filter, which the candidates of `pos` are picked from. A measure of
+    /// the work for `pos`, cheap to get.
+    pub(crate) fn window(&self, pos: usize) -> usize {
+        let end = if self.filtered {
+            self.length_window(pos).1
+        } else {
+            self.order.len()
+        };
+        match self.split {
+            Some(split) => {
+                let rights = self.rights_before[end] - self.rights_before[pos + 1];
+                if self.order[pos] < split {
+                    rights
+                } else {
+                    end - pos - 1 - rights
+                }
+            }
+            None => end - pos - 1,
+        }
+    }
+
+    /// Key indices that may be similar to the key at sorted position `pos`,
+    /// only counting keys after it in the sorted order so every pair is
+    /// visited once.
+    pub(crate) fn candidates(&self, pos: usize) -> Vec<usize> {
+        if !self.filtered {
+            // Not sorted, the right keys are the last ones
+            let from = match self.split {
+                Some(split) if pos < split => split,
+                Some(_) => return Vec::new(),
+                None => pos + 1,
+            };
+            return self.order[from..].to_vec();
+        }
+        let len = self.lengths[pos];
+        let (upper, end) = self.length_window(pos);
+        let max_distance = match self.max_distance(upper) {
+            Some(max_distance) => max_distance,
+            None => upper,
+        };
+
+        // Bigrams the key shares with every candidate in the window
+        let min_shared = len as isize + 1 - Q as isize - (Q * max_distance) as isize;
+
+        let mut found = Vec::new();
+        if min_shared > 0 {
+            let mut shared: HashMap<usize, usize> = HashMap::new();
+            for (gram, count) in &self.grams[pos] {
+                let posting = &self.postings[gram];
+                let start = posting.partition_point(|&(p, _)| p <= pos);
+                for &(other, other_count) in posting[start..].iter().take_while(|(p, _)| *p < end) {
+                    if self.crosses(pos, other) {
+                        *shared.entry(other).or_insert(0) += (*count).min(other_count);
+                    }
+                }
+            }
+            found.extend(
+                shared
+                    .into_iter()
+                    .filter(|&(other, count)| self.may_be_similar(pos, other, Some(count)))
+                    .map(|(other, _)| other),
+            );
+            found.sort_unstable();
+        } else {
+            found.extend(
+                (pos + 1..end)
+                    .filter(|&other| self.crosses(pos, other))
+                    .filter(|&other| self.may_be_similar(pos, other, None)),
+            );
+        }
+        found.into_iter().map(|other| self.order[other]).collect()
+    }
+
+    /// Cheap per pair checks, `shared` being the number of common bigrams when
+    /// they were counted.
+    fn may_be_similar(&self, pos: usize, other: usize, shared: Option<usize>) -> bool {
+        let (len, other_len) = (self.lengths[pos], self.lengths[other]);
+        let max_distance = match self.max_distance(len.max(other_len)) {
+            Some(max_distance) => max_distance,
+            None => return false,
+        };
+        if len.abs_diff(other_len) > max_distance {
+            return false;
+        }
+        match shared {
+            Some(shared) => {
+                let min_shared = len.max(other_len) as isize + 1 - Q as isize;
+                min_shared - (Q * max_distance) as isize <= shared as isize
+            }
+            None => true,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub(crate) fn key_at(&self, pos: usize) -> usize {
+        self.order[pos]
+    }
+}
+
+/// Number of threads to use for a requested `threads`, `0` meaning one per core.
+pub(crate) fn worker_threads(threads: usize) -> usize {
+    if threads > 0 {
+        return threads;
+    }
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// For every row, the indices of the other rows similar to it with their
+/// similarity, in ascending order of index. Only the `candidates` pairs are
+/// passed to `similarity`, which returns `None` for pairs that are not similar.
+///
+/// The comparisons are spread over `threads` workers (`0` for one per core);
+/// the result does not depend on the number of threads. The pairs passing the
+/// length filter are counted in `progress` as they are checked, every pair
+/// when there is no filter; `None` when it is cancelled before the end.
+pub(crate) fn similar_pairs<F>(
+    candidates: &Candidates,
+    similarity: F,
+    threads: usize,
+    progress: &Progress,
+) -> Option<Vec<Vec<(usize, f64)>>>
+where
+    F: Fn(usize, usize) -> Option<f64> + Sync,
+{
+    let threads = worker_threads(threads).min(candidates.len()).max(1);
+    progress.set_total(
+        (0..candidates.len())
+            .map(|pos| candidates.window(pos))
+            .sum(),
+    );
+
+    // Workers take small chunks of sorted positions, the amount of work per
+    // position varies too much to split the keys evenly up front.
+    let next_chunk = AtomicUsize::new(0);
+    let work = || {
+        let mut pairs = Vec::new();
+        loop {
+            let start = next_chunk.fetch_add(CHUNK, Ordering::Relaxed);
+            if start >= candidates.len() {
+                return pairs;
+            }
+            for pos in start..candidates.len().min(start + CHUNK) {
+                if progress.is_cancelled() {
+                    return pairs;
+                }
+                let left = candidates.key_at(pos);
+                for right in candidates.candidates(pos) {
+                    if let Some(score) = similarity(left, right) {
+                        pairs.push((left, right, score));
+                    }
+                }
+                progress.advance(candidates.window(pos));
+            }
+        }
+    };
+    let pairs: Vec<Vec<(usize, usize, f64)>> = if threads == 1 {
+        vec![work()]
+    } else {
+        thread::scope(|s| {
+            let workers: Vec<_> = (0..threads).map(|_| s.spawn(work)).collect();
+            workers
+                .into_iter()
+                .map(|w| w.join().expect("similarity worker panicked"))
+                .collect()
+        })
+    };
+
+    if progress.is_cancelled() {
+        return None;
+    }
+
+    let mut neighbours: Vec<Vec<(usize, f64)>> = vec![Vec::new(); candidates.len()];
+    for (left, right, score) in pairs.into_iter().flatten() {
+        neighbours[left].push((right, score));
+        neighbours[right].push((left, score));
+    }
+    for n in neighbours.iter_mut() {
+        n.sort_unstable_by_key(|&(i, _)| i);
+    }
+    Some(neighbours)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_distance::{Metric, SimilarityMetric};
+    use crate::testing::Lcg;
+
+    /// Short keys over a small alphabet, including multi-byte and combining
+    /// characters so the units disagree on lengths.
+    fn keys() -> Vec<String> {
+        let alphabet = ["a", "b", "c", "é", "e\u{301}", "中", "文", " "];
+        let mut rng = Lcg::new(0x2545_f491_4f6c_dd1d);
+        let mut keys: Vec<String> = (0..200)
+            .map(|_| {
+                (0..rng.below(10))
+                    .map(|_| alphabet[rng.below(alphabet.len())])
+                    .collect()
+            })
+            .collect();
+        // Near copies, so that high thresholds have pairs to find
+        for i in 0..60 {
+            let mut key = keys[i].clone();
+            key.push_str(alphabet[rng.below(alphabet.len())]);
+            keys.push(key);
+        }
+        keys
+    }
+
+    #[test]
+    fn filters_keep_every_similar_pair() {
+        let keys = keys();
+        for unit in LengthUnit::ALL {
+            let metric = Metric::Levenshtein.in_unit(unit);
+            for similarity in [0.0, 30.0, 50.0, 66.67, 75.0, 80.0, 90.0, 100.0] {
+                let mut all = Vec::new();
+                for i in 0..keys.len() {
+                    for j in i + 1..keys.len() {
+                        if metric.similarity(&keys[i], &keys[j]) >= similarity {
+                            all.push((i, j));
+                        }
+                    }
+                }
+
+                let candidates = Candidates::new(&keys, similarity, unit);
+                let mut filtered = Vec::new();
+                for pos in 0..candidates.len() {
+                    let left = candidates.key_at(pos);
+                    for right in candidates.candidates(pos) {
+                        if metric.similarity(&keys[left], &keys[right]) >= similarity {
+                            filtered.push((left.min(right), left.max(right)));
+                        }
+                    }
+                }
+                filtered.sort_unstable();
+                all.sort_unstable();
+                assert_eq!(filtered, all, "{:?} at {}", unit, similarity);
+            }
+        }
+    }
+}
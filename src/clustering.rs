@@ -0,0 +1,281 @@
+//! Turning the similar pairs of rows into groups.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+/// How similar pairs of rows are turned into groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum Clustering {
+    /// Every row not grouped yet, in order, seeds a group with the not yet
+    /// grouped rows similar to it. Fast, but the groups depend on the row order.
+    #[default]
+    Greedy,
+    /// Rows are grouped when they are linked by a chain of similar pairs, so
+    /// A~B and B~C puts A, B and C together.
+    ConnectedComponents,
+    /// Every pair of rows in a group is similar. Groups are merged most similar
+    /// first, ties are broken by row order.
+    CompleteLinkage,
+}
+
+impl Clustering {
+    pub const ALL: [Clustering; 3] = [
+        Clustering::Greedy,
+        Clustering::ConnectedComponents,
+        Clustering::CompleteLinkage,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Clustering::Greedy => "Greedy",
+            Clustering::ConnectedComponents => "Connected components",
+            Clustering::CompleteLinkage => "Complete linkage",
+        }
+    }
+}
+
+/// Group rows given, for every row, the other rows similar to it with their
+/// similarity, sorted by row index.
+pub(crate) fn cluster(neighbours: &[Vec<(usize, f64)>], clustering: Clustering) -> Vec<Vec<usize>> {
+    match clustering {
+        Clustering::Greedy => greedy(neighbours),
+        Clustering::ConnectedComponents => connected_components(neighbours),
+        Clustering::CompleteLinkage => complete_linkage(neighbours),
+    }
+}
+
+fn greedy(neighbours: &[Vec<(usize, f64)>]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut visited: Vec<bool> = vec![false; neighbours.len()];
+    for seed in 0..neighbours.len() {
+        // Rows already in a group do not seed their own
+        if visited[seed] {
+            continue;
+        }
+        visited[seed] = true;
+        let mut group = vec![seed];
+        for &(i, _) in &neighbours[seed] {
+            if !visited[i] {
+                group.push(i);
+                visited[i] = true;
+            }
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+/// Disjoint sets of rows, every set being identified by its smallest row.
+pub(crate) struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    pub(crate) fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+        }
+    }
+
+    pub(crate) fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    pub(crate) fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a.max(b)] = a.min(b);
+    }
+
+    /// The sets, ordered by their smallest row, with their rows in order.
+    fn into_groups(mut self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut group_of: HashMap<usize, usize> = HashMap::new();
+        for i in 0..self.parent.len() {
+            let root = self.find(i);
+            let idx = *group_of.entry(root).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[idx].push(i);
+        }
+        groups
+    }
+}
+
+fn connected_components(neighbours: &[Vec<(usize, f64)>]) -> Vec<Vec<usize>> {
+    let mut sets = UnionFind::new(neighbours.len());
+    for (i, similar) in neighbours.iter().enumerate() {
+        for &(j, _) in similar {
+            sets.union(i, j);
+        }
+    }
+    sets.into_groups()
+}
+
+/// Links between two groups: how many of their cross pairs are similar, and
+/// the lowest similarity among them.
+#[derive(Clone, Copy)]
+struct Link {
+    pairs: usize,
+    min: f64,
+}
+
+/// A similarity ordered by [`f64::total_cmp`], so merges can be kept in a heap.
+#[derive(Clone, Copy, PartialEq)]
+struct Score(f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+fn complete_linkage(neighbours: &[Vec<(usize, f64)>]) -> Vec<Vec<usize>> {
+    let len = neighbours.len();
+    // Groups are identified by their smallest row
+    let mut members: Vec<Vec<usize>> = (0..len).map(|i| vec![i]).collect();
+    let mut links: Vec<HashMap<usize, Link>> = vec![HashMap::new(); len];
+    // Candidate merges: best similarity first, then the smallest rows
+    let mut merges = BinaryHeap::new();
+    for (i, similar) in neighbours.iter().enumerate() {
+        for &(j, score) in similar {
+            links[i].insert(
+                j,
+                Link {
+                    pairs: 1,
+                    min: score,
+                },
+            );
+            if i < j {
+                merges.push((Score(score), Reverse((i, j))));
+            }
+        }
+    }
+
+    let mut sets = UnionFind::new(len);
+    while let Some((Score(score), Reverse((a, b)))) = merges.pop() {
+        // Skip merges made stale by earlier merges
+        let link = match links[a].get(&b) {
+            Some(link) if link.min == score => *link,
+            _ => continue,
+        };
+        if members[a].is_empty()
+            || members[b].is_empty()
+            || link.pairs != members[a].len() * members[b].len()
+        {
+            continue;
+        }
+
+        // Merge `b` into `a`, combining the links of both to every other group
+        sets.union(a, b);
+        let moved = std::mem::take(&mut members[b]);
+        members[a].extend(moved);
+        let b_links = std::mem::take(&mut links[b]);
+        links[a].remove(&b);
+        for (other, b_link) in b_links {
+            if other == a {
+                continue;
+            }
+            links[other].remove(&b);
+            let merged = match links[a].get(&other) {
+                Some(a_link) => Link {
+                    pairs: a_link.pairs + b_link.pairs,
+                    min: a_link.min.min(b_link.min),
+                },
+                None => b_link,
+            };
+            links[a].insert(other, merged);
+            links[other].insert(a, merged);
+        }
+        for (&other, link) in &links[a] {
+            if link.pairs == members[a].len() * members[other].len() {
+                merges.push((Score(link.min), Reverse((a.min(other), a.max(other)))));
+            }
+        }
+    }
+    sets.into_groups()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Lcg;
+
+    /// Random similar pairs between `len` rows, with scores of a few distinct
+    /// values so that ties happen.
+    fn neighbours(len: usize, seed: u64) -> Vec<Vec<(usize, f64)>> {
+        let mut rng = Lcg::new(seed);
+        let mut neighbours = vec![Vec::new(); len];
+        for i in 0..len {
+            for j in i + 1..len {
+                if rng.below(4) == 0 {
+                    let score = 80.0 + rng.below(5) as f64 * 5.0;
+                    neighbours[i].push((j, score));
+                    neighbours[j].push((i, score));
+                }
+            }
+        }
+        neighbours
+    }
+
+    fn assert_partition(groups: &[Vec<usize>], len: usize) {
+        let mut rows: Vec<usize> = groups.iter().flatten().copied().collect();
+        rows.sort_unstable();
+        assert_eq!(rows, (0..len).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn complete_linkage_groups_only_similar_pairs() {
+        for seed in 0..20 {
+            let neighbours = neighbours(40, seed);
+            let groups = cluster(&neighbours, Clustering::CompleteLinkage);
+            assert_partition(&groups, neighbours.len());
+            assert!(groups.iter().any(|g| g.len() > 2), "seed {}", seed);
+            for group in &groups {
+                for (k, &a) in group.iter().enumerate() {
+                    for &b in &group[k + 1..] {
+                        assert!(
+                            neighbours[a].iter().any(|&(i, _)| i == b),
+                            "{} and {} are not similar, seed {}",
+                            a,
+                            b,
+                            seed
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn connected_components_split_only_unlinked_rows() {
+        for seed in 0..20 {
+            let neighbours = neighbours(40, seed);
+            let groups = cluster(&neighbours, Clustering::ConnectedComponents);
+            assert_partition(&groups, neighbours.len());
+            let mut group_of = vec![0; neighbours.len()];
+            for (g, group) in groups.iter().enumerate() {
+                for &row in group {
+                    group_of[row] = g;
+                }
+            }
+            for (i, similar) in neighbours.iter().enumerate() {
+                for &(j, _) in similar {
+                    assert_eq!(group_of[i], group_of[j], "seed {}", seed);
+                }
+            }
+        }
+    }
+}
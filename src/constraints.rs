@@ -0,0 +1,369 @@
+//! Review decisions kept across runs: pairs of rows that must, or must not,
+//! end up in the same group.
+//!
+//! Decisions are stored in a sidecar file next to the table, keyed by the
+//! values of a column identifying the rows, so they survive a reordered or
+//! extended table and a change of the grouping settings.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::clustering::UnionFind;
+use crate::grouping::Table;
+
+/// Constraints on rows given by their index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RowConstraints {
+    /// Rows grouped together whatever their similarity.
+    pub must_link: BTreeSet<(usize, usize)>,
+    /// Rows never grouped together.
+    pub cannot_link: BTreeSet<(usize, usize)>,
+}
+
+/// A pair ordered smallest first, so each pair is stored once.
+fn pair<T: Ord>(a: T, b: T) -> (T, T) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+impl RowConstraints {
+    pub fn is_empty(&self) -> bool {
+        self.must_link.is_empty() && self.cannot_link.is_empty()
+    }
+
+    /// Group `a` and `b` together, replacing an earlier opposite decision.
+    pub fn add_must_link(&mut self, a: usize, b: usize) {
+        if a != b {
+            self.cannot_link.remove(&pair(a, b));
+            self.must_link.insert(pair(a, b));
+        }
+    }
+
+    /// Keep `a` and `b` apart, replacing an earlier opposite decision.
+    pub fn add_cannot_link(&mut self, a: usize, b: usize) {
+        if a != b {
+            self.must_link.remove(&pair(a, b));
+            self.cannot_link.insert(pair(a, b));
+        }
+    }
+
+    /// Add the decisions of `other`, replacing earlier opposite ones.
+    pub fn extend(&mut self, other: &RowConstraints) {
+        for &(a, b) in &other.must_link {
+            self.add_must_link(a, b);
+        }
+        for &(a, b) in &other.cannot_link {
+            self.add_cannot_link(a, b);
+        }
+    }
+
+    /// Make the similar pairs of `neighbours` agree with the constraints:
+    /// must-link pairs are similar, cannot-link pairs are not.
+    pub(crate) fn constrain_neighbours(&self, neighbours: &mut [Vec<(usize, f64)>]) {
+        let rows = neighbours.len();
+        for &(a, b) in &self.cannot_link {
+            if a < rows && b < rows {
+                neighbours[a].retain(|&(i, _)| i != b);
+                neighbours[b].retain(|&(i, _)| i != a);
+            }
+        }
+        for &(a, b) in &self.must_link {
+            if a < rows && b < rows {
+                for (row, other) in [(a, b), (b, a)] {
+                    match neighbours[row].binary_search_by_key(&other, |&(i, _)| i) {
+                        Ok(pos) => neighbours[row][pos].1 = 100.0,
+                        Err(pos) => neighbours[row].insert(pos, (other, 100.0)),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Repair `groups` of `rows` rows so every constraint holds: rows that must
+    /// be linked are moved to the first group of any of them, then groups with
+    /// rows that cannot be linked are split. Must-link wins over contradicting
+    /// cannot-links.
+    pub(crate) fn enforce(&self, groups: Vec<Vec<usize>>, rows: usize) -> Vec<Vec<usize>> {
+        if self.is_empty() {
+            return groups;
+        }
+        let mut linked = UnionFind::new(rows);
+        for &(a, b) in &self.must_link {
+            if a < rows && b < rows {
+                linked.union(a, b);
+            }
+        }
+
+        // Every must-link set joins the first group holding one of its rows
+        let mut home: HashMap<usize, usize> = HashMap::new();
+        for (idx, group) in groups.iter().enumerate() {
+            for &row in group {
+                home.entry(linked.find(row)).or_insert(idx);
+            }
+        }
+        let mut regrouped: Vec<Vec<usize>> = vec![Vec::new(); groups.len()];
+        for row in groups.into_iter().flatten() {
+            regrouped[home[&linked.find(row)]].push(row);
+        }
+        regrouped.retain(|g| !g.is_empty());
+
+        if self.cannot_link.is_empty() {
+            return regrouped;
+        }
+        let mut cannot = BTreeSet::new();
+        for &(a, b) in &self.cannot_link {
+            if a < rows && b < rows {
+                let (a, b) = (linked.find(a), linked.find(b));
+                if a != b {
+                    cannot.insert(pair(a, b));
+                }
+            }
+        }
+
+        // Split every group: each must-link set joins the first part it has no
+        // cannot-link with
+        let mut split = Vec::with_capacity(regrouped.len());
+        for group in regrouped {
+            let mut parts: Vec<(Vec<usize>, Vec<usize>)> = Vec::new();
+            let mut part_of: HashMap<usize, usize> = HashMap::new();
+            for &row in &group {
+                let set = linked.find(row);
+                let idx = match part_of.get(&set) {
+                    Some(&idx) => idx,
+                    None => {
+                        let idx = parts
+                            .iter()
+                            .position(|(part_sets, _)| {
+                                part_sets.iter().all(|&s| !cannot.contains(&pair(s, set)))
+                            })
+                            .unwrap_or_else(|| {
+                                parts.push((Vec::new(), Vec::new()));
+                                parts.len() - 1
+                            });
+                        parts[idx].0.push(set);
+                        part_of.insert(set, idx);
+                        idx
+                    }
+                };
+                parts[idx].1.push(row);
+            }
+            split.extend(parts.into_iter().map(|(_, rows)| rows));
+        }
+        split
+    }
+}
+
+/// Review decisions keyed by the values of the `id_column` column, as stored
+/// in the sidecar file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Constraints {
+    /// Header of the column identifying the rows.
+    pub id_column: String,
+    pub must_link: BTreeSet<(String, String)>,
+    pub cannot_link: BTreeSet<(String, String)>,
+}
+
+impl Constraints {
+    pub fn new(id_column: &str) -> Self {
+        Self {
+            id_column: id_column.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Where the decisions about `table_file` are kept: `customers.csv` has
+    /// them in `customers.constraints.json`.
+    pub fn sidecar_path(table_file: &Path) -> PathBuf {
+        table_file.with_extension("constraints.json")
+    }
+
+    pub fn load(path: &Path) -> Result<Self, std::io::Error> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+        let writer = BufWriter::new(File::create(path)?);
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+
+    fn id_column_index(&self, table: &Table) -> Result<usize, String> {
+        table.column_index(&self.id_column).ok_or(format!(
+            "No id column {:?} in {:?}",
+            self.id_column, table.file
+        ))
+    }
+
+    /// Add the decisions made on the rows of `table`, later decisions about a
+    /// pair replacing earlier ones.
+    pub fn add(&mut self, table: &Table, decisions: &RowConstraints) -> Result<(), String> {
+        let col_idx = self.id_column_index(table)?;
+        let id = |row: usize| table.rows[row][col_idx].clone();
+        for &(a, b) in &decisions.must_link {
+            let ids = pair(id(a), id(b));
+            if ids.0 != ids.1 {
+                self.cannot_link.remove(&ids);
+                self.must_link.insert(ids);
+            }
+        }
+        for &(a, b) in &decisions.cannot_link {
+            let ids = pair(id(a), id(b));
+            if ids.0 != ids.1 {
+                self.must_link.remove(&ids);
+                self.cannot_link.insert(ids);
+            }
+        }
+        Ok(())
+    }
+
+    /// The constraints on the rows of `table`. Ids that are not in the table
+    /// are skipped, rows sharing an id are all constrained.
+    pub fn resolve(&self, table: &Table) -> Result<RowConstraints, String> {
+        let col_idx = self.id_column_index(table)?;
+        let mut rows_of: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (row, values) in table.rows.iter().enumerate() {
+            rows_of
+                .entry(values[col_idx].as_str())
+                .or_default()
+                .push(row);
+        }
+        let rows = |id: &String| rows_of.get(id.as_str()).map_or(&[][..], |r| r.as_slice());
+
+        let mut resolved = RowConstraints::default();
+        for (a, b) in &self.must_link {
+            for &i in rows(a) {
+                for &j in rows(b) {
+                    resolved.add_must_link(i, j);
+                }
+            }
+        }
+        for (a, b) in &self.cannot_link {
+            for &i in rows(a) {
+                for &j in rows(b) {
+                    resolved.add_cannot_link(i, j);
+                }
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::Clustering;
+    use crate::grouping::{group, GroupingConfig};
+
+    fn table(rows: &[(&str, &str)]) -> Table {
+        Table {
+            file: PathBuf::from("fruits.csv"),
+            headers: vec![String::from("id"), String::from("name")],
+            rows: rows
+                .iter()
+                .map(|&(id, name)| vec![id.to_owned(), name.to_owned()])
+                .collect(),
+        }
+    }
+
+    fn fruits() -> Table {
+        table(&[
+            ("1", "apple"),
+            ("2", "apples"),
+            ("3", "mango"),
+            ("4", "mangos"),
+            ("5", "kiwi"),
+        ])
+    }
+
+    fn group_of(groups: &[Vec<usize>], row: usize) -> usize {
+        groups.iter().position(|g| g.contains(&row)).unwrap()
+    }
+
+    #[test]
+    fn must_links_are_merged_and_cannot_links_split() {
+        let table = fruits();
+        for clustering in Clustering::ALL {
+            let mut config = GroupingConfig {
+                col_idx: 1,
+                similarity: 80.0,
+                clustering,
+                ..Default::default()
+            };
+            let groups = group(&table, &config);
+            assert_eq!(group_of(&groups, 0), group_of(&groups, 1));
+            assert_eq!(group_of(&groups, 2), group_of(&groups, 3));
+            assert_ne!(group_of(&groups, 0), group_of(&groups, 4));
+
+            config.constraints.add_must_link(0, 4);
+            config.constraints.add_cannot_link(2, 3);
+            let groups = group(&table, &config);
+            assert_eq!(
+                group_of(&groups, 0),
+                group_of(&groups, 4),
+                "{:?}",
+                clustering
+            );
+            assert_ne!(
+                group_of(&groups, 2),
+                group_of(&groups, 3),
+                "{:?}",
+                clustering
+            );
+        }
+    }
+
+    #[test]
+    fn must_link_wins_over_cannot_link() {
+        let mut constraints = RowConstraints::default();
+        constraints.add_must_link(0, 1);
+        constraints.add_must_link(1, 2);
+        constraints.cannot_link.insert((0, 2));
+        let groups = constraints.enforce(vec![vec![0, 1], vec![2], vec![3]], 4);
+        assert_eq!(groups, vec![vec![0, 1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn sidecar_keeps_decisions_by_id() {
+        let fruits = fruits();
+        let mut decisions = RowConstraints::default();
+        decisions.add_must_link(0, 4);
+        decisions.add_cannot_link(2, 3);
+        let mut constraints = Constraints::new("id");
+        constraints.add(&fruits, &decisions).unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "similar_row_{}.constraints.json",
+            std::process::id()
+        ));
+        constraints.save(&path).unwrap();
+        let loaded = Constraints::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, constraints);
+        assert_eq!(loaded.resolve(&fruits), Ok(decisions));
+
+        // Reordered and extended, the decisions follow the ids
+        let reordered = table(&[
+            ("4", "mangos"),
+            ("6", "pear"),
+            ("5", "kiwi"),
+            ("3", "mango"),
+            ("1", "apple"),
+        ]);
+        let resolved = loaded.resolve(&reordered).unwrap();
+        assert_eq!(resolved.must_link, BTreeSet::from([(2, 4)]));
+        assert_eq!(resolved.cannot_link, BTreeSet::from([(0, 3)]));
+    }
+
+    #[test]
+    fn unknown_id_column_is_an_error() {
+        let constraints = Constraints::new("customer");
+        assert!(constraints.resolve(&fruits()).is_err());
+    }
+}
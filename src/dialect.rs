@@ -0,0 +1,200 @@
+//! The CSV variant of a file: delimiter, quoting, header row and comments, and
+//! guessing it from the start of the file.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::encoding::TextEncoding;
+
+/// How a CSV file is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct CsvDialect {
+    /// The text encoding, decoded to UTF-8 when the file is read.
+    pub encoding: TextEncoding,
+    pub delimiter: u8,
+    pub quote: u8,
+    /// Escapes quotes inside quoted fields, e.g. `\`. Without it quotes are
+    /// escaped by doubling them.
+    pub escape: Option<u8>,
+    /// Whether the first record holds the column names. Without it the columns
+    /// are named `Column 1`, `Column 2`...
+    pub has_headers: bool,
+    /// Lines starting with it are skipped, e.g. `#`.
+    pub comment: Option<u8>,
+    /// Allow records of different lengths, short ones are padded with empty
+    /// values.
+    pub flexible: bool,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            encoding: TextEncoding::Utf8,
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            has_headers: true,
+            comment: None,
+            flexible: false,
+        }
+    }
+}
+
+/// The delimiters tried by [`CsvDialect::sniff`], with their names.
+pub const DELIMITERS: [(u8, &str); 5] = [
+    (b',', "Comma"),
+    (b';', "Semicolon"),
+    (b'\t', "Tab"),
+    (b'|', "Pipe"),
+    (b' ', "Space"),
+];
+
+/// How much of a file [`CsvDialect::sniff_file`] looks at.
+const SNIFF_BYTES: u64 = 64 * 1024;
+
+/// Records of a sample compared by [`CsvDialect::sniff`].
+const SNIFF_RECORDS: usize = 50;
+
+impl CsvDialect {
+    pub fn reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape)
+            .double_quote(self.escape.is_none())
+            .comment(self.comment)
+            // The headers are read as a record, so they can be generated
+            .has_headers(false)
+            .flexible(self.flexible);
+        builder
+    }
+
+    /// Guess the dialect of the start of a file, its encoding included. The
+    /// last line of `sample` may be cut short.
+    pub fn sniff(sample: &[u8]) -> Self {
+        // Drop the possibly partial last line
+        let sample = match sample.iter().rposition(|&b| b == b'\n') {
+            Some(end) if end + 1 < sample.len() => &sample[..=end],
+            _ => sample,
+        };
+        // Multi-byte encodings may have delimiters and quotes inside characters
+        let encoding = TextEncoding::detect(sample);
+        let decoded = encoding.decode(sample);
+        let sample = decoded.as_bytes();
+        let starts_with = |prefix: &[u8]| {
+            sample
+                .split(|&b| b == b'\n')
+                .any(|line| line.starts_with(prefix))
+        };
+        let count = |pattern: &[u8]| {
+            sample
+                .windows(pattern.len())
+                .filter(|w| w == &pattern)
+                .count()
+        };
+
+        let mut dialect = CsvDialect {
+            encoding,
+            comment: if starts_with(b"#") { Some(b'#') } else { None },
+            ..Default::default()
+        };
+        if count(b"\"") == 0 && count(b"'") > 0 && starts_with(b"'") {
+            dialect.quote = b'\'';
+        }
+        let mut escaped = vec![b'\\'];
+        escaped.push(dialect.quote);
+        if count(&escaped) > 0 {
+            dialect.escape = Some(b'\\');
+        }
+
+        // The delimiter splitting the most records into the same number of
+        // fields, more than one
+        let mut best = (0, 0);
+        // Spaces separate words too often to be guessed
+        for (delimiter, _) in DELIMITERS.into_iter().filter(|(d, _)| *d != b' ') {
+            let candidate = CsvDialect {
+                delimiter,
+                flexible: true,
+                ..dialect
+            };
+            let lengths: Vec<usize> = candidate
+                .reader_builder()
+                .from_reader(sample)
+                .records()
+                .take(SNIFF_RECORDS)
+                .map_while(Result::ok)
+                .map(|r| r.len())
+                .collect();
+            let Some(&fields) = lengths
+                .iter()
+                .max_by_key(|&&n| (lengths.iter().filter(|&&m| m == n).count(), n))
+            else {
+                continue;
+            };
+            let consistent = lengths.iter().filter(|&&n| n == fields).count();
+            if fields > 1 && (consistent, fields) > best {
+                best = (consistent, fields);
+                dialect.delimiter = delimiter;
+                dialect.flexible = consistent < lengths.len();
+            }
+        }
+
+        dialect.has_headers = dialect.sniff_headers(sample);
+        dialect
+    }
+
+    /// Whether the first record of `sample` looks like column names: a column
+    /// of numbers under a name votes for, a number over them votes against.
+    fn sniff_headers(&self, sample: &[u8]) -> bool {
+        let mut records = self
+            .reader_builder()
+            .flexible(true)
+            .from_reader(sample)
+            .into_records()
+            .take(SNIFF_RECORDS)
+            .map_while(Result::ok);
+        let Some(first) = records.next() else {
+            return true;
+        };
+        let rest: Vec<csv::StringRecord> = records.collect();
+        if rest.is_empty() {
+            return true;
+        }
+        let is_number = |v: &str| v.trim().parse::<f64>().is_ok();
+        let mut votes = 0;
+        for (col_idx, head) in first.iter().enumerate() {
+            let mut values = rest
+                .iter()
+                .filter_map(|r| r.get(col_idx))
+                .filter(|v| !v.trim().is_empty())
+                .peekable();
+            if values.peek().is_some() && values.all(is_number) {
+                votes += if is_number(head) { -1 } else { 1 };
+            }
+        }
+        votes >= 0
+    }
+
+    /// [`sniff`](Self::sniff) the start of the file at `path`.
+    pub fn sniff_file(path: &Path) -> Result<Self, std::io::Error> {
+        let mut sample = Vec::new();
+        File::open(path)?
+            .take(SNIFF_BYTES)
+            .read_to_end(&mut sample)?;
+        Ok(Self::sniff(&sample))
+    }
+}
+
+/// The name of a delimiter, or the delimiter itself.
+pub fn delimiter_name(delimiter: u8) -> String {
+    DELIMITERS
+        .iter()
+        .find(|(d, _)| *d == delimiter)
+        .map_or_else(
+            || (delimiter as char).to_string(),
+            |(_, name)| name.to_string(),
+        )
+}
@@ -4,32 +4,509 @@ use unicode_segmentation::UnicodeSegmentation;
 pub fn levenshtein_distance(left: &str, right: &str) -> usize {
     let l: Vec<&str> = left.graphemes(true).collect::<Vec<&str>>();
     let r: Vec<&str> = right.graphemes(true).collect::<Vec<&str>>();
-    let rows = r.len() + 1;
+    let max_distance = std::cmp::max(l.len(), r.len());
+    bounded_distance(&l, &r, max_distance).expect("distance never exceeds the longer length")
+}
+
+/// Levenshtein distance of `left` and `right` if it is at most `max_distance`,
+/// `None` otherwise.
+///
+/// Only the diagonal band of width `2 * max_distance + 1` is computed and the
+/// computation stops as soon as every cell of a row exceeds `max_distance`.
+pub fn levenshtein_distance_bounded(left: &str, right: &str, max_distance: usize) -> Option<usize> {
+    let l: Vec<&str> = left.graphemes(true).collect::<Vec<&str>>();
+    let r: Vec<&str> = right.graphemes(true).collect::<Vec<&str>>();
+    bounded_distance(&l, &r, max_distance)
+}
+
+fn bounded_distance<T: PartialEq>(l: &[T], r: &[T], max_distance: usize) -> Option<usize> {
+    if l.len().abs_diff(r.len()) > max_distance {
+        return None;
+    }
+    // The distance never exceeds the longer length, this also keeps `over` from overflowing
+    let max_distance = min(max_distance, std::cmp::max(l.len(), r.len()));
+    // Any value above the bound is stored as `over`
+    let over = max_distance + 1;
+
+    // Two rolling rows of the dp table, a row per grapheme of `r`
     let cols = l.len() + 1;
-    let mut dp_table = vec![vec![0; cols]; rows];
+    let mut prev: Vec<usize> = (0..cols).map(|col| min(col, over)).collect();
+    let mut cur: Vec<usize> = vec![over; cols];
 
-    // Init the first row and the first col of the dp_table
-    for i in 0..rows {
-        dp_table[i][0] = i;
+    for row in 1..r.len() + 1 {
+        // Cells further than `max_distance` from the diagonal are always above the bound
+        let from = std::cmp::max(row.saturating_sub(max_distance), 1);
+        let to = min(row + max_distance, l.len());
+        cur[from - 1] = if from == 1 { min(row, over) } else { over };
+
+        let mut row_min = cur[from - 1];
+        for col in from..to + 1 {
+            let value = if l[col - 1] == r[row - 1] {
+                prev[col - 1]
+            } else {
+                min(prev[col - 1], min(prev[col], cur[col - 1])) + 1
+            };
+            cur[col] = min(value, over);
+            row_min = min(row_min, cur[col]);
+        }
+        // The band of the next row reaches one column further
+        if to < l.len() {
+            cur[to + 1] = over;
+        }
+
+        if row_min > max_distance {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut cur);
     }
 
-    for i in 0..cols {
-        dp_table[0][i] = i;
+    Some(prev[cols - 1]).filter(|d| *d <= max_distance)
+}
+
+/// What lengths and edits are counted in when scoring similarity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum LengthUnit {
+    /// User-perceived characters: a CJK character and an `é` written with a
+    /// combining accent both count as one.
+    #[default]
+    Graphemes,
+    /// Unicode scalar values.
+    Chars,
+    /// UTF-8 bytes, a CJK character counts as three.
+    Bytes,
+}
+
+impl LengthUnit {
+    pub const ALL: [LengthUnit; 3] = [LengthUnit::Graphemes, LengthUnit::Chars, LengthUnit::Bytes];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LengthUnit::Graphemes => "Graphemes",
+            LengthUnit::Chars => "Chars",
+            LengthUnit::Bytes => "Bytes",
+        }
+    }
+
+    /// The units of `s`, as byte slices so that every unit compares alike.
+    pub fn split<'a>(&self, s: &'a str) -> Vec<&'a [u8]> {
+        match self {
+            LengthUnit::Graphemes => s.graphemes(true).map(str::as_bytes).collect(),
+            LengthUnit::Chars => s
+                .char_indices()
+                .map(|(i, c)| &s.as_bytes()[i..i + c.len_utf8()])
+                .collect(),
+            LengthUnit::Bytes => s.as_bytes().chunks(1).collect(),
+        }
+    }
+
+    /// Length of `s` in this unit.
+    pub fn count(&self, s: &str) -> usize {
+        match self {
+            LengthUnit::Graphemes => s.graphemes(true).count(),
+            LengthUnit::Chars => s.chars().count(),
+            LengthUnit::Bytes => s.len(),
+        }
     }
+}
 
-    // walk through
-    for row in 1..rows {
+/// Optimal string alignment distance: Levenshtein distance where swapping two
+/// adjacent graphemes also counts as a single edit.
+pub fn damerau_levenshtein_distance(left: &str, right: &str) -> usize {
+    let l: Vec<&str> = left.graphemes(true).collect::<Vec<&str>>();
+    let r: Vec<&str> = right.graphemes(true).collect::<Vec<&str>>();
+    osa_distance(&l, &r)
+}
+
+fn osa_distance<T: PartialEq>(l: &[T], r: &[T]) -> usize {
+    let cols = l.len() + 1;
+
+    // Three rolling rows, a transposition looks two rows back
+    let mut before: Vec<usize> = vec![0; cols];
+    let mut prev: Vec<usize> = (0..cols).collect();
+    let mut cur: Vec<usize> = vec![0; cols];
+    for row in 1..r.len() + 1 {
+        cur[0] = row;
         for col in 1..cols {
-            if l[col - 1] == r[row - 1] {
-                dp_table[row][col] = dp_table[row - 1][col - 1];
-            } else {
-                dp_table[row][col] = min(
-                    dp_table[row - 1][col - 1],
-                    min(dp_table[row - 1][col], dp_table[row][col - 1]),
-                ) + 1;
+            let cost = usize::from(l[col - 1] != r[row - 1]);
+            cur[col] = min(prev[col - 1] + cost, min(prev[col], cur[col - 1]) + 1);
+            if row > 1 && col > 1 && l[col - 1] == r[row - 2] && l[col - 2] == r[row - 1] {
+                cur[col] = min(cur[col], before[col - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut prev);
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[cols - 1]
+}
+
+/// Jaro-Winkler similarity of the graphemes of `left` and `right`, `0.0..=1.0`.
+pub fn jaro_winkler_similarity(left: &str, right: &str) -> f64 {
+    let l: Vec<&str> = left.graphemes(true).collect::<Vec<&str>>();
+    let r: Vec<&str> = right.graphemes(true).collect::<Vec<&str>>();
+    jaro_winkler(&l, &r)
+}
+
+fn jaro_winkler<T: PartialEq>(l: &[T], r: &[T]) -> f64 {
+    if l.is_empty() && r.is_empty() {
+        return 1.0;
+    }
+    if l.is_empty() || r.is_empty() {
+        return 0.0;
+    }
+
+    // Units match when equal and not further apart than the window
+    let window = (std::cmp::max(l.len(), r.len()) / 2).saturating_sub(1);
+    let mut l_matched = vec![false; l.len()];
+    let mut r_matched = vec![false; r.len()];
+    let mut matches = 0;
+    for (i, g) in l.iter().enumerate() {
+        let from = i.saturating_sub(window);
+        let to = min(i + window + 1, r.len());
+        for j in from..to {
+            if !r_matched[j] && r[j] == *g {
+                l_matched[i] = true;
+                r_matched[j] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+    if matches == 0 {
+        return 0.0;
+    }
+
+    // Matched units that are not in the same order
+    let l_order = l.iter().zip(&l_matched).filter(|(_, m)| **m);
+    let r_order = r.iter().zip(&r_matched).filter(|(_, m)| **m);
+    let transpositions = l_order
+        .zip(r_order)
+        .filter(|((a, _), (b, _))| a != b)
+        .count()
+        / 2;
+
+    let m = matches as f64;
+    let jaro = (m / l.len() as f64 + m / r.len() as f64 + (m - transpositions as f64) / m) / 3.0;
+
+    // Boost close strings sharing a prefix of up to 4 units
+    if jaro <= 0.7 {
+        return jaro;
+    }
+    let prefix = l.iter().zip(r).take(4).take_while(|(a, b)| a == b).count();
+    jaro + prefix as f64 * 0.1 * (1.0 - jaro)
+}
+
+/// Token set ratio in percent: the words of both strings are compared as sets,
+/// so reordered or repeated words do not lower the score.
+pub fn token_set_ratio(left: &str, right: &str) -> f64 {
+    token_set(left, right, LengthUnit::Graphemes)
+}
+
+fn token_set(left: &str, right: &str, unit: LengthUnit) -> f64 {
+    let mut l: Vec<&str> = left.split_whitespace().collect();
+    let mut r: Vec<&str> = right.split_whitespace().collect();
+    l.sort_unstable();
+    l.dedup();
+    r.sort_unstable();
+    r.dedup();
+
+    let common: Vec<&str> = l.iter().filter(|w| r.contains(w)).cloned().collect();
+    let only_l: Vec<&str> = l.iter().filter(|w| !r.contains(w)).cloned().collect();
+    let only_r: Vec<&str> = r.iter().filter(|w| !l.contains(w)).cloned().collect();
+
+    let common = common.join(" ");
+    let with_l = [common.as_str(), &only_l.join(" ")]
+        .join(" ")
+        .trim()
+        .to_owned();
+    let with_r = [common.as_str(), &only_r.join(" ")]
+        .join(" ")
+        .trim()
+        .to_owned();
+    let ratio = |a: &str, b: &str| Levenshtein { unit }.similarity(a, b);
+    if common.is_empty() {
+        return ratio(&with_l, &with_r);
+    }
+    ratio(&common, &with_l)
+        .max(ratio(&common, &with_r))
+        .max(ratio(&with_l, &with_r))
+}
+
+/// Jaccard index in percent of the sets of grapheme bigrams of both strings.
+/// A string shorter than two graphemes is its own single gram.
+pub fn ngram_jaccard(left: &str, right: &str) -> f64 {
+    let l: Vec<&str> = left.graphemes(true).collect::<Vec<&str>>();
+    let r: Vec<&str> = right.graphemes(true).collect::<Vec<&str>>();
+    jaccard(&l, &r)
+}
+
+fn jaccard<T: Ord>(l: &[T], r: &[T]) -> f64 {
+    fn grams<T: Ord>(units: &[T]) -> Vec<&[T]> {
+        let mut grams: Vec<&[T]> = if units.len() < 2 {
+            vec![units]
+        } else {
+            units.windows(2).collect()
+        };
+        grams.sort_unstable();
+        grams.dedup();
+        grams
+    }
+    let l = grams(l);
+    let r = grams(r);
+    let common = l.iter().filter(|g| r.binary_search(g).is_ok()).count();
+    let union = l.len() + r.len() - common;
+    if union == 0 {
+        return 100.0;
+    }
+    common as f64 * 100.0 / union as f64
+}
+
+/// Turn an edit distance into a similarity percent relative to `max_len`.
+fn percent(distance: usize, max_len: usize) -> f64 {
+    // Meaning that both strings are empty
+    if max_len == 0 {
+        return 100.0;
+    }
+    max_len.saturating_sub(distance) as f64 * 100.0 / max_len as f64
+}
+
+/// The most edits two strings may differ by to reach `similarity` percent when
+/// the longer one is `max_len` units long, `None` when it cannot be reached.
+pub(crate) fn max_distance(max_len: usize, similarity: f64) -> Option<usize> {
+    if percent(0, max_len) < similarity {
+        return None;
+    }
+    let estimate = max_len as f64 * (100.0 - similarity.max(0.0)) / 100.0;
+    let mut distance = min(estimate.floor() as usize, max_len);
+    // The estimate may be off by one through rounding, `percent` decides
+    while distance > 0 && percent(distance, max_len) < similarity {
+        distance -= 1;
+    }
+    while distance < max_len && percent(distance + 1, max_len) >= similarity {
+        distance += 1;
+    }
+    Some(distance)
+}
+
+/// A way of scoring how similar two strings are.
+pub trait SimilarityMetric {
+    /// Similarity of `left` and `right` in percent, `0.0..=100.0`.
+    fn similarity(&self, left: &str, right: &str) -> f64;
+
+    /// The similarity if it reaches `similarity`, `None` otherwise. Metrics
+    /// that can stop early once the threshold is out of reach override this.
+    fn similarity_at_least(&self, left: &str, right: &str, similarity: f64) -> Option<f64> {
+        Some(self.similarity(left, right)).filter(|s| *s >= similarity)
+    }
+}
+
+/// Edit distance relative to the length of the longer string, both counted
+/// in `unit`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Levenshtein {
+    pub unit: LengthUnit,
+}
+
+impl SimilarityMetric for Levenshtein {
+    fn similarity(&self, left: &str, right: &str) -> f64 {
+        let (l, r) = (self.unit.split(left), self.unit.split(right));
+        let max_len = std::cmp::max(l.len(), r.len());
+        let distance =
+            bounded_distance(&l, &r, max_len).expect("distance never exceeds the longer length");
+        percent(distance, max_len)
+    }
+
+    fn similarity_at_least(&self, left: &str, right: &str, similarity: f64) -> Option<f64> {
+        let (l, r) = (self.unit.split(left), self.unit.split(right));
+        let max_len = std::cmp::max(l.len(), r.len());
+        let distance = bounded_distance(&l, &r, max_distance(max_len, similarity)?)?;
+        Some(percent(distance, max_len))
+    }
+}
+
+/// Like [`Levenshtein`], with adjacent transpositions counting as one edit.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DamerauLevenshtein {
+    pub unit: LengthUnit,
+}
+
+impl SimilarityMetric for DamerauLevenshtein {
+    fn similarity(&self, left: &str, right: &str) -> f64 {
+        let (l, r) = (self.unit.split(left), self.unit.split(right));
+        percent(osa_distance(&l, &r), std::cmp::max(l.len(), r.len()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JaroWinkler {
+    pub unit: LengthUnit,
+}
+
+impl SimilarityMetric for JaroWinkler {
+    fn similarity(&self, left: &str, right: &str) -> f64 {
+        jaro_winkler(&self.unit.split(left), &self.unit.split(right)) * 100.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenSetRatio {
+    pub unit: LengthUnit,
+}
+
+impl SimilarityMetric for TokenSetRatio {
+    fn similarity(&self, left: &str, right: &str) -> f64 {
+        token_set(left, right, self.unit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NgramJaccard {
+    pub unit: LengthUnit,
+}
+
+impl SimilarityMetric for NgramJaccard {
+    fn similarity(&self, left: &str, right: &str) -> f64 {
+        jaccard(&self.unit.split(left), &self.unit.split(right))
+    }
+}
+
+/// The metric used for grouping, selectable in the settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum Metric {
+    #[default]
+    Levenshtein,
+    DamerauLevenshtein,
+    JaroWinkler,
+    TokenSetRatio,
+    NgramJaccard,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 5] = [
+        Metric::Levenshtein,
+        Metric::DamerauLevenshtein,
+        Metric::JaroWinkler,
+        Metric::TokenSetRatio,
+        Metric::NgramJaccard,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Levenshtein => "Levenshtein",
+            Metric::DamerauLevenshtein => "Damerau-Levenshtein",
+            Metric::JaroWinkler => "Jaro-Winkler",
+            Metric::TokenSetRatio => "Token set ratio",
+            Metric::NgramJaccard => "Bigram Jaccard",
+        }
+    }
+
+    /// This metric counting lengths and edits in `unit`.
+    pub fn in_unit(self, unit: LengthUnit) -> UnitMetric {
+        UnitMetric { metric: self, unit }
+    }
+}
+
+/// A [`Metric`] together with the unit it counts in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UnitMetric {
+    pub metric: Metric,
+    pub unit: LengthUnit,
+}
+
+impl SimilarityMetric for UnitMetric {
+    fn similarity(&self, left: &str, right: &str) -> f64 {
+        let unit = self.unit;
+        match self.metric {
+            Metric::Levenshtein => Levenshtein { unit }.similarity(left, right),
+            Metric::DamerauLevenshtein => DamerauLevenshtein { unit }.similarity(left, right),
+            Metric::JaroWinkler => JaroWinkler { unit }.similarity(left, right),
+            Metric::TokenSetRatio => TokenSetRatio { unit }.similarity(left, right),
+            Metric::NgramJaccard => NgramJaccard { unit }.similarity(left, right),
+        }
+    }
+
+    fn similarity_at_least(&self, left: &str, right: &str, similarity: f64) -> Option<f64> {
+        match self.metric {
+            Metric::Levenshtein => {
+                Levenshtein { unit: self.unit }.similarity_at_least(left, right, similarity)
+            }
+            _ => Some(self.similarity(left, right)).filter(|s| *s >= similarity),
+        }
+    }
+}
+
+/// Counts in graphemes, see [`Metric::in_unit`] for the other units.
+impl SimilarityMetric for Metric {
+    fn similarity(&self, left: &str, right: &str) -> f64 {
+        self.in_unit(LengthUnit::default()).similarity(left, right)
+    }
+
+    fn similarity_at_least(&self, left: &str, right: &str, similarity: f64) -> Option<f64> {
+        self.in_unit(LengthUnit::default())
+            .similarity_at_least(left, right, similarity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Lcg;
+
+    /// Levenshtein distance from the full dynamic programming table.
+    fn full_distance(l: &[char], r: &[char]) -> usize {
+        let mut table = vec![vec![0; r.len() + 1]; l.len() + 1];
+        table[0] = (0..=r.len()).collect();
+        for (i, row) in table.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        for i in 1..=l.len() {
+            for j in 1..=r.len() {
+                let cost = usize::from(l[i - 1] != r[j - 1]);
+                table[i][j] = min(
+                    table[i - 1][j - 1] + cost,
+                    min(table[i - 1][j], table[i][j - 1]) + 1,
+                );
+            }
+        }
+        table[l.len()][r.len()]
+    }
+
+    fn words() -> Vec<Vec<char>> {
+        let mut rng = Lcg::new(0x9e37_79b9_7f4a_7c15);
+        (0..80)
+            .map(|_| {
+                (0..rng.below(12))
+                    .map(|_| b"abcab"[rng.below(5)] as char)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bounded_distance_matches_full_table() {
+        let words = words();
+        for l in &words {
+            for r in &words {
+                let distance = full_distance(l, r);
+                for bound in 0..=l.len().max(r.len()) + 1 {
+                    let expected = Some(distance).filter(|&d| d <= bound);
+                    assert_eq!(bounded_distance(l, r, bound), expected, "{:?} {:?}", l, r);
+                }
             }
         }
     }
 
-    return dp_table[rows - 1][cols - 1];
-}
\ No newline at end of file
+    #[test]
+    fn similarity_at_least_matches_similarity() {
+        let words: Vec<String> = words().into_iter().map(String::from_iter).collect();
+        let metric = Levenshtein::default();
+        for l in &words {
+            for r in &words {
+                let similarity = metric.similarity(l, r);
+                for threshold in [0.0, 50.0, 66.67, 80.0, 90.0, 100.0] {
+                    let expected = Some(similarity).filter(|&s| s >= threshold);
+                    assert_eq!(metric.similarity_at_least(l, r, threshold), expected);
+                }
+            }
+        }
+    }
+}
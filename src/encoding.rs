@@ -0,0 +1,144 @@
+//! Text encodings of the csv files, decoded to UTF-8 when read and encoded
+//! back when written.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+/// The text encoding of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    /// UTF-8 starting with a byte order mark, which Excel needs to recognize
+    /// UTF-8 csv files. Reading strips the mark in any UTF-8 file.
+    Utf8WithBom,
+    /// Simplified Chinese, a superset of GBK and GB2312.
+    Gb18030,
+    Gbk,
+    /// Traditional Chinese.
+    Big5,
+    ShiftJis,
+    EucKr,
+    /// Central European.
+    Windows1250,
+    /// Cyrillic.
+    Windows1251,
+    /// Western European, also read for ISO-8859-1 (Latin-1).
+    Windows1252,
+}
+
+impl TextEncoding {
+    pub const ALL: [TextEncoding; 10] = [
+        TextEncoding::Utf8,
+        TextEncoding::Utf8WithBom,
+        TextEncoding::Gb18030,
+        TextEncoding::Gbk,
+        TextEncoding::Big5,
+        TextEncoding::ShiftJis,
+        TextEncoding::EucKr,
+        TextEncoding::Windows1250,
+        TextEncoding::Windows1251,
+        TextEncoding::Windows1252,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf8WithBom => "UTF-8 with BOM",
+            TextEncoding::Gb18030 => "GB18030",
+            TextEncoding::Gbk => "GBK",
+            TextEncoding::Big5 => "Big5",
+            TextEncoding::ShiftJis => "Shift_JIS",
+            TextEncoding::EucKr => "EUC-KR",
+            TextEncoding::Windows1250 => "Windows-1250",
+            TextEncoding::Windows1251 => "Windows-1251",
+            TextEncoding::Windows1252 => "Windows-1252",
+        }
+    }
+
+    fn encoding(&self) -> &'static Encoding {
+        match self {
+            TextEncoding::Utf8 | TextEncoding::Utf8WithBom => encoding_rs::UTF_8,
+            TextEncoding::Gb18030 => encoding_rs::GB18030,
+            TextEncoding::Gbk => encoding_rs::GBK,
+            TextEncoding::Big5 => encoding_rs::BIG5,
+            TextEncoding::ShiftJis => encoding_rs::SHIFT_JIS,
+            TextEncoding::EucKr => encoding_rs::EUC_KR,
+            TextEncoding::Windows1250 => encoding_rs::WINDOWS_1250,
+            TextEncoding::Windows1251 => encoding_rs::WINDOWS_1251,
+            TextEncoding::Windows1252 => encoding_rs::WINDOWS_1252,
+        }
+    }
+
+    fn is_utf8(&self) -> bool {
+        matches!(self, TextEncoding::Utf8 | TextEncoding::Utf8WithBom)
+    }
+
+    /// Guess the encoding of the start of a file, which may end in the middle
+    /// of a character. Valid UTF-8 is taken as UTF-8, other text is guessed from
+    /// the frequencies of its characters.
+    pub fn detect(sample: &[u8]) -> Self {
+        match std::str::from_utf8(sample) {
+            Ok(_) => return TextEncoding::Utf8,
+            // Only the last character is cut short
+            Err(e) if e.error_len().is_none() => return TextEncoding::Utf8,
+            Err(_) => {}
+        }
+        let mut detector = chardetng::EncodingDetector::new();
+        detector.feed(sample, false);
+        let guess = detector.guess(None, true);
+        if guess == encoding_rs::GBK {
+            // Decodes the same, and more
+            return TextEncoding::Gb18030;
+        }
+        TextEncoding::ALL
+            .into_iter()
+            .find(|e| !e.is_utf8() && e.encoding() == guess)
+            .unwrap_or(TextEncoding::Windows1252)
+    }
+
+    /// `bytes` as UTF-8. Bytes that are not valid in this encoding are
+    /// replaced by `�`.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        self.encoding().decode(bytes).0.into_owned()
+    }
+
+    /// A reader of the UTF-8 text of `file`. UTF-8 files are read as they are,
+    /// so invalid UTF-8 is still reported by the csv reader.
+    pub(crate) fn reader<'a>(&self, file: impl Read + 'a) -> Box<dyn Read + 'a> {
+        if self.is_utf8() {
+            Box::new(file)
+        } else {
+            Box::new(
+                DecodeReaderBytesBuilder::new()
+                    .encoding(Some(self.encoding()))
+                    .build(file),
+            )
+        }
+    }
+
+    /// Write `text` to `path` in this encoding. Fails when the encoding has no
+    /// character for some of the text, as Windows-1252 has none for `中`.
+    pub fn write(&self, path: &Path, text: &str) -> Result<(), std::io::Error> {
+        let (bytes, _, unmappable) = self.encoding().encode(text);
+        if unmappable {
+            let c = text
+                .chars()
+                .find(|c| self.encoding().encode(c.encode_utf8(&mut [0; 4])).2)
+                .unwrap_or_default();
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{:?} cannot be written in {}", c, self.name()),
+            ));
+        }
+        let mut file = File::create(path)?;
+        if *self == TextEncoding::Utf8WithBom {
+            file.write_all(b"\xEF\xBB\xBF")?;
+        }
+        file.write_all(&bytes)
+    }
+}
@@ -0,0 +1,322 @@
+//! Machine readable exports of a grouping result: JSON, JSON Lines and Parquet,
+//! next to the csv and xlsx ones.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use parquet::basic::{LogicalType, Repetition, Type as PhysicalType};
+use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use serde::ser::{SerializeMap, SerializeStruct};
+use serde::{Serialize, Serializer};
+
+use crate::encoding::TextEncoding;
+use crate::grouping::{write_table_with, GroupingConfig, Table};
+use crate::xlsx::write_xlsx;
+
+/// The file formats the grouped rows can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum ExportFormat {
+    /// The rows group by group, with a blank row between groups.
+    #[default]
+    Csv,
+    /// A workbook with a colour per group and a summary sheet.
+    Xlsx,
+    /// An array of groups, each with its row indices and rows.
+    Json,
+    /// A row per line, with its group.
+    JsonLines,
+    /// A columnar table of the rows, with their group.
+    Parquet,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 5] = [
+        ExportFormat::Csv,
+        ExportFormat::Xlsx,
+        ExportFormat::Json,
+        ExportFormat::JsonLines,
+        ExportFormat::Parquet,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Xlsx => "XLSX",
+            ExportFormat::Json => "JSON",
+            ExportFormat::JsonLines => "JSON Lines",
+            ExportFormat::Parquet => "Parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Xlsx => "xlsx",
+            ExportFormat::Json => "json",
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    /// The format of `path` by its extension, csv when unknown.
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        match extension.as_str() {
+            "ndjson" => ExportFormat::JsonLines,
+            extension => ExportFormat::ALL
+                .into_iter()
+                .find(|f| f.extension() == extension)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The column names of `headers` made unique, for formats keying values by
+/// name: a name already taken, by an earlier column or by one of `reserved`,
+/// gets ` (2)`, ` (3)`... appended.
+fn unique_names(headers: &[String], reserved: &[&str]) -> Vec<String> {
+    let mut taken: HashSet<String> = reserved.iter().map(|&name| name.to_owned()).collect();
+    headers
+        .iter()
+        .map(|header| {
+            let mut name = header.clone();
+            let mut n = 1;
+            while taken.contains(&name) {
+                n += 1;
+                name = format!("{} ({})", header, n);
+            }
+            taken.insert(name.clone());
+            name
+        })
+        .collect()
+}
+
+/// A row as an object of its values by column name, in column order.
+struct Record<'a> {
+    headers: &'a [String],
+    values: &'a [String],
+}
+
+impl Serialize for Record<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.headers.len()))?;
+        for (header, value) in self.headers.iter().zip(self.values) {
+            map.serialize_entry(header, value)?;
+        }
+        map.end()
+    }
+}
+
+/// A group as `{"group_id": 0, "row_indices": [0, 3], "rows": [{...}, {...}]}`.
+struct JsonGroup<'a> {
+    table: &'a Table,
+    headers: &'a [String],
+    group_id: usize,
+    group: &'a [usize],
+}
+
+impl Serialize for JsonGroup<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let rows: Vec<Record<'_>> = self
+            .group
+            .iter()
+            .map(|&r| Record {
+                headers: self.headers,
+                values: &self.table.rows[r],
+            })
+            .collect();
+        let mut s = serializer.serialize_struct("Group", 3)?;
+        s.serialize_field("group_id", &self.group_id)?;
+        s.serialize_field("row_indices", self.group)?;
+        s.serialize_field("rows", &rows)?;
+        s.end()
+    }
+}
+
+/// A row of a JSON Lines file, `{"group_id": 0, "row_index": 3, "row": {...}}`.
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    group_id: usize,
+    row_index: usize,
+    row: Record<'a>,
+}
+
+/// Write the groups as a JSON array of groups, each with its id, the indices
+/// of its rows and its rows as objects keyed by column name. Repeated column
+/// names are made unique.
+pub fn write_json(path: &Path, table: &Table, groups: &[Vec<usize>]) -> Result<(), std::io::Error> {
+    let headers = unique_names(&table.headers, &[]);
+    let groups: Vec<JsonGroup<'_>> = groups
+        .iter()
+        .enumerate()
+        .map(|(group_id, group)| JsonGroup {
+            table,
+            headers: &headers,
+            group_id,
+            group,
+        })
+        .collect();
+    let mut file = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut file, &groups)?;
+    file.flush()
+}
+
+/// Write a JSON object per row and line, with its group id, its row index and
+/// the row keyed by column name. Repeated column names are made unique.
+pub fn write_json_lines(
+    path: &Path,
+    table: &Table,
+    groups: &[Vec<usize>],
+) -> Result<(), std::io::Error> {
+    let headers = unique_names(&table.headers, &[]);
+    let mut file = BufWriter::new(File::create(path)?);
+    for (group_id, group) in groups.iter().enumerate() {
+        for &row_index in group {
+            let line = JsonLine {
+                group_id,
+                row_index,
+                row: Record {
+                    headers: &headers,
+                    values: &table.rows[row_index],
+                },
+            };
+            serde_json::to_writer(&mut file, &line)?;
+            file.write_all(b"\n")?;
+        }
+    }
+    file.flush()
+}
+
+fn parquet_error(e: parquet::errors::ParquetError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
+/// Write the rows group by group to a Parquet file, with `group_id` and
+/// `row_index` columns in front of the columns of the table, as strings.
+/// Column names repeated, or taken by the added columns, are made unique.
+pub fn write_parquet(
+    path: &Path,
+    table: &Table,
+    groups: &[Vec<usize>],
+) -> Result<(), std::io::Error> {
+    write_parquet_file(path, table, groups).map_err(parquet_error)
+}
+
+fn write_parquet_file(
+    path: &Path,
+    table: &Table,
+    groups: &[Vec<usize>],
+) -> Result<(), parquet::errors::ParquetError> {
+    let integer = |name: &str| {
+        Type::primitive_type_builder(name, PhysicalType::INT64)
+            .with_repetition(Repetition::REQUIRED)
+            .build()
+            .map(Arc::new)
+    };
+    let mut fields = vec![integer("group_id")?, integer("row_index")?];
+    for header in &unique_names(&table.headers, &["group_id", "row_index"]) {
+        fields.push(Arc::new(
+            Type::primitive_type_builder(header, PhysicalType::BYTE_ARRAY)
+                .with_repetition(Repetition::REQUIRED)
+                .with_logical_type(Some(LogicalType::String))
+                .build()?,
+        ));
+    }
+    let schema = Type::group_type_builder("groups")
+        .with_fields(fields)
+        .build()?;
+
+    let rows: Vec<(i64, usize)> = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(group_id, group)| group.iter().map(move |&r| (group_id as i64, r)))
+        .collect();
+    let mut writer = SerializedFileWriter::new(
+        File::create(path)?,
+        Arc::new(schema),
+        Arc::new(WriterProperties::builder().build()),
+    )?;
+    let mut row_group = writer.next_row_group()?;
+    let mut col_idx = 0;
+    while let Some(mut column) = row_group.next_column()? {
+        match col_idx {
+            0 | 1 => {
+                let values: Vec<i64> = rows
+                    .iter()
+                    .map(|&(group_id, r)| if col_idx == 0 { group_id } else { r as i64 })
+                    .collect();
+                column
+                    .typed::<Int64Type>()
+                    .write_batch(&values, None, None)?;
+            }
+            _ => {
+                let values: Vec<ByteArray> = rows
+                    .iter()
+                    .map(|&(_, r)| ByteArray::from(table.rows[r][col_idx - 2].as_str()))
+                    .collect();
+                column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, None, None)?;
+            }
+        }
+        column.close()?;
+        col_idx += 1;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Write the grouped rows to `path` in `format`. Only csv files are written in
+/// `encoding`, the other formats are always UTF-8.
+pub fn write_groups(
+    path: &Path,
+    format: ExportFormat,
+    table: &Table,
+    groups: &[Vec<usize>],
+    config: &GroupingConfig,
+    encoding: TextEncoding,
+) -> Result<(), std::io::Error> {
+    match format {
+        ExportFormat::Csv => write_table_with(path, table, groups, encoding),
+        ExportFormat::Xlsx => write_xlsx(path, table, groups, config),
+        ExportFormat::Json => write_json(path, table, groups),
+        ExportFormat::JsonLines => write_json_lines(path, table, groups),
+        ExportFormat::Parquet => write_parquet(path, table, groups),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_names_suffix_taken_names() {
+        let headers: Vec<String> = ["name", "group_id", "name", "name (2)", "city", "name"]
+            .iter()
+            .map(|&h| h.to_owned())
+            .collect();
+        assert_eq!(
+            unique_names(&headers, &["group_id", "row_index"]),
+            [
+                "name",
+                "group_id (2)",
+                "name (2)",
+                "name (2) (2)",
+                "city",
+                "name (3)"
+            ]
+        );
+        assert_eq!(unique_names(&headers[..1], &[]), ["name"]);
+    }
+}
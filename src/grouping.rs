@@ -0,0 +1,713 @@
+//! The GUI-independent grouping engine: reading and writing tables, and grouping
+//! their rows by the similarity of one column.
+//!
+//! Build the crate with `default-features = false` to use it without egui/eframe.
+
+use crate::candidates::{similar_pairs, Candidates};
+use crate::clustering::{cluster, Clustering};
+use crate::constraints::RowConstraints;
+use crate::dialect::CsvDialect;
+use crate::edit_distance::{LengthUnit, Levenshtein, Metric, SimilarityMetric};
+use crate::encoding::TextEncoding;
+use crate::normalize::Normalization;
+use crate::phonetic::Phonetic;
+use crate::progress::{Progress, ProgressReader};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use csv;
+
+/// A csv file loaded into memory.
+#[derive(Debug)]
+pub struct Table {
+    pub file: PathBuf,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Find a column by its header name, or by its zero based index.
+    pub fn column_index(&self, column: &str) -> Option<usize> {
+        if let Some(idx) = self.headers.iter().position(|h| h == column) {
+            return Some(idx);
+        }
+        column
+            .parse::<usize>()
+            .ok()
+            .filter(|idx| *idx < self.headers.len())
+    }
+
+    /// Values of the given column, one per row.
+    pub fn keys(&self, col_idx: usize) -> Vec<String> {
+        self.rows.iter().map(|v| v[col_idx].to_owned()).collect()
+    }
+}
+
+pub fn read_table(csv: PathBuf) -> Result<Table, std::io::Error> {
+    read_table_with(csv, &CsvDialect::default())
+}
+
+/// Read a csv file written in `dialect`.
+pub fn read_table_with(csv: PathBuf, dialect: &CsvDialect) -> Result<Table, std::io::Error> {
+    read_rows(csv, dialect, usize::MAX, &Progress::new())
+}
+
+/// Same as [`read_table_with`], counting the bytes read in `progress`. Fails
+/// once `progress` is cancelled.
+pub fn read_table_with_progress(
+    csv: PathBuf,
+    dialect: &CsvDialect,
+    progress: &Progress,
+) -> Result<Table, std::io::Error> {
+    read_rows(csv, dialect, usize::MAX, progress)
+}
+
+/// The first `rows` rows of a csv file written in `dialect`, to preview it
+/// before loading.
+pub fn preview_table(
+    csv: PathBuf,
+    dialect: &CsvDialect,
+    rows: usize,
+) -> Result<Table, std::io::Error> {
+    read_rows(csv, dialect, rows, &Progress::new())
+}
+
+fn read_rows(
+    csv: PathBuf,
+    dialect: &CsvDialect,
+    limit: usize,
+    progress: &Progress,
+) -> Result<Table, std::io::Error> {
+    let file = std::fs::File::open(&csv)?;
+    progress.set_total(file.metadata()?.len() as usize);
+    let mut rdr = dialect
+        .reader_builder()
+        .from_reader(dialect.encoding.reader(ProgressReader::new(file, progress)));
+    let mut records = rdr.records();
+    let mut headers: Vec<String> = Vec::new();
+    if dialect.has_headers {
+        if let Some(record) = records.next() {
+            headers = record?.iter().map(String::from).collect();
+        }
+    }
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    for record in records.take(limit) {
+        rows.push(record?.iter().map(String::from).collect());
+    }
+
+    // Every row has a value for every column, even in flexible files
+    let width = rows.iter().map(Vec::len).fold(headers.len(), usize::max);
+    for idx in headers.len()..width {
+        headers.push(format!("Column {}", idx + 1));
+    }
+    for row in &mut rows {
+        row.resize(width, String::new());
+    }
+    Ok(Table {
+        headers,
+        rows,
+        file: csv,
+    })
+}
+
+/// One of the columns rows are compared on.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ColumnMatch {
+    pub col_idx: usize,
+    pub metric: Metric,
+    /// Weight of this column in the combined similarity.
+    pub weight: usize,
+    /// Minimum similarity of this column alone, in percent.
+    pub similarity: f64,
+}
+
+impl Default for ColumnMatch {
+    fn default() -> Self {
+        Self {
+            col_idx: 0,
+            metric: Metric::Levenshtein,
+            weight: 1,
+            similarity: 0.0,
+        }
+    }
+}
+
+/// How rows are compared and grouped.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct GroupingConfig {
+    /// The column whose values are compared.
+    pub col_idx: usize,
+    /// Minimum similarity in percent, `0.0..=100.0`.
+    pub similarity: f64,
+    pub case_sensitive: bool,
+    /// How the similarity of two values is scored.
+    pub metric: Metric,
+    /// What the metrics count lengths and edits in.
+    pub unit: LengthUnit,
+    /// Worker threads for the pairwise comparison, `0` for one per core.
+    pub threads: usize,
+    /// When not empty, rows are compared on all of these columns instead of
+    /// `col_idx` alone. Two rows are similar when every column reaches its own
+    /// threshold and the weighted average reaches `similarity`.
+    pub columns: Vec<ColumnMatch>,
+    /// How similar pairs of rows are turned into groups.
+    pub clustering: Clustering,
+    /// Leave out the groups of a single row, keeping only duplicates.
+    pub drop_singletons: bool,
+    /// Applied to the values of the compared columns before comparison.
+    pub normalization: Normalization,
+    /// Compare phonetic keys of the normalized values instead of the values.
+    pub phonetic: Phonetic,
+    /// Review decisions on rows of the grouped table, honoured whatever the
+    /// similarity. Resolved from a sidecar file, see [`crate::Constraints`].
+    #[serde(skip)]
+    pub constraints: RowConstraints,
+}
+
+impl Default for GroupingConfig {
+    fn default() -> Self {
+        Self {
+            col_idx: 0,
+            similarity: 100.0,
+            case_sensitive: true,
+            metric: Metric::Levenshtein,
+            unit: LengthUnit::Graphemes,
+            threads: 0,
+            columns: Vec::new(),
+            clustering: Clustering::Greedy,
+            drop_singletons: false,
+            normalization: Normalization::default(),
+            phonetic: Phonetic::Off,
+            constraints: RowConstraints::default(),
+        }
+    }
+}
+
+impl GroupingConfig {
+    /// The compared columns, `col_idx` with `metric` when no columns are set.
+    pub fn column_matches(&self) -> Vec<ColumnMatch> {
+        if !self.columns.is_empty() {
+            return self.columns.clone();
+        }
+        vec![ColumnMatch {
+            col_idx: self.col_idx,
+            metric: self.metric,
+            weight: 1,
+            similarity: 0.0,
+        }]
+    }
+
+    /// Whether every compared column is a column of `table`, which
+    /// [`group`] and [`compared_columns`] expect. Column indices saved with
+    /// the settings of another table may not be.
+    pub fn check_columns(&self, table: &Table) -> Result<(), String> {
+        match self
+            .column_matches()
+            .iter()
+            .find(|m| m.col_idx >= table.headers.len())
+        {
+            Some(m) => Err(format!(
+                "Column {} is compared but {:?} has {} columns",
+                m.col_idx + 1,
+                table.file,
+                table.headers.len()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// A value of a compared column as it is compared: normalized, turned
+    /// into its phonetic key, and lowercased unless comparing case sensitively.
+    pub fn prepare_key(&self, key: &str) -> String {
+        let key = self.phonetic.key(&self.normalization.apply(key));
+        if self.case_sensitive {
+            key
+        } else {
+            key.to_lowercase()
+        }
+    }
+
+    /// The combined similarity of two rows, given the values of their compared
+    /// columns, if the rows are similar.
+    pub fn row_similarity(&self, left: &[&str], right: &[&str]) -> Option<f64> {
+        let matches = self.column_matches();
+        combined_similarity(
+            &matches,
+            self.unit,
+            self.similarity,
+            |i| left[i],
+            |i| right[i],
+        )
+    }
+}
+
+/// Weighted average of the column similarities, if every column reaches its
+/// own threshold and the average reaches `similarity`. `left` and `right` give
+/// the value of the `i`th compared column of either row.
+fn combined_similarity<'a>(
+    matches: &[ColumnMatch],
+    unit: LengthUnit,
+    similarity: f64,
+    left: impl Fn(usize) -> &'a str,
+    right: impl Fn(usize) -> &'a str,
+) -> Option<f64> {
+    let mut weighted = 0.0;
+    let mut weights = 0;
+    for (i, m) in matches.iter().enumerate() {
+        let column_similarity =
+            m.metric
+                .in_unit(unit)
+                .similarity_at_least(left(i), right(i), m.similarity)?;
+        weighted += column_similarity * m.weight as f64;
+        weights += m.weight;
+    }
+    let combined = if weights == 0 {
+        100.0
+    } else {
+        weighted / weights as f64
+    };
+    Some(combined).filter(|s| *s >= similarity)
+}
+
+/// The lowest similarity of the `i`th column that still lets the weighted
+/// average reach `similarity`.
+fn column_threshold(matches: &[ColumnMatch], i: usize, similarity: f64) -> f64 {
+    let weights: usize = matches.iter().map(|m| m.weight).sum();
+    let m = &matches[i];
+    if m.weight == 0 {
+        return m.similarity;
+    }
+    // The other columns score 100 at best; the margin absorbs rounding so no
+    // pair passing the combined threshold is filtered out
+    let needed = (similarity * weights as f64 - 100.0 * (weights - m.weight) as f64)
+        / m.weight as f64
+        - 1e-9;
+    m.similarity.max(needed)
+}
+
+/// Values of the compared columns of `table`, one `Vec` per column of
+/// [`GroupingConfig::column_matches`].
+///
+/// Panics when a compared column is not in `table`, see
+/// [`GroupingConfig::check_columns`].
+pub fn compared_columns(table: &Table, config: &GroupingConfig) -> Vec<Vec<String>> {
+    config
+        .column_matches()
+        .iter()
+        .map(|m| table.keys(m.col_idx))
+        .collect()
+}
+
+/// Group the rows of `table`, returning the row indices of every group.
+///
+/// Panics when a compared column is not in `table`, see
+/// [`GroupingConfig::check_columns`].
+pub fn group(table: &Table, config: &GroupingConfig) -> Vec<Vec<usize>> {
+    group_columns(&compared_columns(table, config), config)
+}
+
+/// Same as [`group`], for the already extracted values of the compared column.
+pub fn group_keys(keys: &[String], config: &GroupingConfig) -> Vec<Vec<usize>> {
+    let config = GroupingConfig {
+        columns: Vec::new(),
+        ..config.clone()
+    };
+    group_columns(&[keys.to_vec()], &config)
+}
+
+/// Same as [`group`], for the already extracted [`compared_columns`].
+pub fn group_columns(columns: &[Vec<String>], config: &GroupingConfig) -> Vec<Vec<usize>> {
+    group_columns_with(columns, config, &Progress::new()).expect("grouping is not cancelled")
+}
+
+/// Same as [`group_columns`], counting the checked pairs of rows in `progress`.
+/// `None` when `progress` is cancelled before the groups are found.
+pub fn group_columns_with(
+    columns: &[Vec<String>],
+    config: &GroupingConfig,
+    progress: &Progress,
+) -> Option<Vec<Vec<usize>>> {
+    Some(group_neighbours(
+        similar_rows(columns, config, progress)?,
+        config,
+    ))
+}
+
+/// Rows whose compared values are identical once prepared, found by hashing
+/// the values so that each distinct value is compared once.
+struct ExactDuplicates {
+    /// For every row, the index of its distinct value in `members`.
+    row_keys: Vec<usize>,
+    /// The rows of every distinct value, in ascending order.
+    members: Vec<Vec<usize>>,
+}
+
+impl ExactDuplicates {
+    fn new(columns: &[Vec<String>]) -> Self {
+        let rows = columns.first().map_or(0, |keys| keys.len());
+        let mut distinct: HashMap<Vec<&str>, usize> = HashMap::new();
+        let mut members: Vec<Vec<usize>> = Vec::new();
+        let row_keys = (0..rows)
+            .map(|row| {
+                let values = columns.iter().map(|keys| keys[row].as_str()).collect();
+                let key = *distinct.entry(values).or_insert_with(|| {
+                    members.push(Vec::new());
+                    members.len() - 1
+                });
+                members[key].push(row);
+                key
+            })
+            .collect();
+        Self { row_keys, members }
+    }
+
+    fn has_duplicates(&self) -> bool {
+        self.members.len() < self.row_keys.len()
+    }
+
+    /// The distinct values of `columns`, in the order of their first row.
+    fn distinct_columns(&self, columns: &[Vec<String>]) -> Vec<Vec<String>> {
+        columns
+            .iter()
+            .map(|keys| self.members.iter().map(|m| keys[m[0]].clone()).collect())
+            .collect()
+    }
+
+    /// The similar rows of every row, given the similar distinct values of
+    /// every distinct value. The rows sharing a value are similar at
+    /// `self_similarity` of it, when it is `Some`.
+    fn expand(
+        &self,
+        neighbours: &[Vec<(usize, f64)>],
+        self_similarity: impl Fn(usize) -> Option<f64>,
+    ) -> Vec<Vec<(usize, f64)>> {
+        let self_similarities: Vec<Option<f64>> = self
+            .members
+            .iter()
+            .enumerate()
+            .map(|(key, rows)| {
+                if rows.len() > 1 {
+                    self_similarity(key)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        self.row_keys
+            .iter()
+            .enumerate()
+            .map(|(row, &key)| {
+                let mut similar: Vec<(usize, f64)> = Vec::new();
+                if let Some(score) = self_similarities[key] {
+                    similar.extend(
+                        self.members[key]
+                            .iter()
+                            .filter(|&&other| other != row)
+                            .map(|&other| (other, score)),
+                    );
+                }
+                for &(other_key, score) in &neighbours[key] {
+                    similar.extend(self.members[other_key].iter().map(|&other| (other, score)));
+                }
+                similar.sort_unstable_by_key(|&(i, _)| i);
+                similar
+            })
+            .collect()
+    }
+}
+
+/// The compared columns of `config` prepared as they are compared.
+fn prepare_columns<'a>(
+    columns: &'a [Vec<String>],
+    config: &GroupingConfig,
+) -> Cow<'a, [Vec<String>]> {
+    if config.case_sensitive
+        && config.normalization.is_identity()
+        && config.phonetic == Phonetic::Off
+    {
+        Cow::Borrowed(columns)
+    } else {
+        Cow::Owned(
+            columns
+                .iter()
+                .map(|keys| keys.iter().map(|k| config.prepare_key(k)).collect())
+                .collect(),
+        )
+    }
+}
+
+/// For every row, the other rows reaching the similarity of `config` with
+/// their similarity, sorted by row index. `None` when `progress` is
+/// cancelled first.
+///
+/// Rows with identical values once prepared are compared once: only the
+/// distinct values are compared, then every row takes the rows of the values
+/// similar to its own.
+pub(crate) fn similar_rows(
+    columns: &[Vec<String>],
+    config: &GroupingConfig,
+    progress: &Progress,
+) -> Option<Vec<Vec<(usize, f64)>>> {
+    let columns = prepare_columns(columns, config);
+    let exact = ExactDuplicates::new(&columns);
+    if !exact.has_duplicates() {
+        return similar_values(&columns, config, progress);
+    }
+    let distinct = exact.distinct_columns(&columns);
+    let neighbours = similar_values(&distinct, config, progress)?;
+    let matches = config.column_matches();
+    Some(exact.expand(&neighbours, |key| {
+        let value = |i: usize| distinct[i][key].as_str();
+        combined_similarity(&matches, config.unit, config.similarity, value, value)
+    }))
+}
+
+/// [`similar_rows`] of already prepared values, comparing every pair.
+fn similar_values(
+    columns: &[Vec<String>],
+    config: &GroupingConfig,
+    progress: &Progress,
+) -> Option<Vec<Vec<(usize, f64)>>> {
+    let rows = columns.first().map_or(0, |keys| keys.len());
+
+    // Only pairs passing the threshold of a Levenshtein column can be similar
+    let matches = config.column_matches();
+    let candidates = match matches.iter().position(|m| m.metric == Metric::Levenshtein) {
+        Some(i) => Candidates::new(
+            &columns[i],
+            column_threshold(&matches, i, config.similarity),
+            config.unit,
+        ),
+        None => Candidates::all(rows),
+    };
+    let similarity = |left: usize, right: usize| {
+        let left_value = |i: usize| columns[i][left].as_str();
+        let right_value = |i: usize| columns[i][right].as_str();
+        combined_similarity(
+            &matches,
+            config.unit,
+            config.similarity,
+            left_value,
+            right_value,
+        )
+    };
+    similar_pairs(&candidates, similarity, config.threads, progress)
+}
+
+/// Group the rows given their [`similar_rows`], honouring the constraints of
+/// `config`.
+pub(crate) fn group_neighbours(
+    mut neighbours: Vec<Vec<(usize, f64)>>,
+    config: &GroupingConfig,
+) -> Vec<Vec<usize>> {
+    let rows = neighbours.len();
+    config.constraints.constrain_neighbours(&mut neighbours);
+    let mut groups = config
+        .constraints
+        .enforce(cluster(&neighbours, config.clustering), rows);
+    if config.drop_singletons {
+        groups.retain(|g| g.len() > 1);
+    }
+    groups
+}
+
+/// The id of the compared values of every row of `table` once prepared, the
+/// same for rows whose values are identical.
+pub fn exact_keys(table: &Table, config: &GroupingConfig) -> Vec<usize> {
+    exact_column_keys(&compared_columns(table, config), config)
+}
+
+/// Same as [`exact_keys`], for the already extracted [`compared_columns`].
+pub fn exact_column_keys(columns: &[Vec<String>], config: &GroupingConfig) -> Vec<usize> {
+    ExactDuplicates::new(&prepare_columns(columns, config)).row_keys
+}
+
+/// The duplicate rows of a grouping result, told apart by whether they
+/// repeat another row of their group exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DuplicateKinds {
+    /// Rows of groups with duplicates whose compared values, once prepared,
+    /// are identical to those of another row of their group.
+    pub exact_rows: usize,
+    /// The other rows of groups with duplicates, only similar to the rest.
+    pub fuzzy_rows: usize,
+}
+
+impl DuplicateKinds {
+    /// The kinds of the duplicate rows of `groups`, given the [`exact_keys`]
+    /// of the grouped table.
+    pub fn new(groups: &[Vec<usize>], exact_keys: &[usize]) -> Self {
+        let mut kinds = Self {
+            exact_rows: 0,
+            fuzzy_rows: 0,
+        };
+        for group in groups.iter().filter(|g| g.len() > 1) {
+            let mut counts: HashMap<usize, usize> = HashMap::new();
+            for &row in group {
+                *counts.entry(exact_keys[row]).or_default() += 1;
+            }
+            for count in counts.into_values() {
+                if count > 1 {
+                    kinds.exact_rows += count;
+                } else {
+                    kinds.fuzzy_rows += count;
+                }
+            }
+        }
+        kinds
+    }
+}
+
+/// Counts describing a grouping result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupStats {
+    /// Groups of two or more rows.
+    pub duplicate_groups: usize,
+    /// Rows in those groups.
+    pub duplicate_rows: usize,
+    /// Rows not similar to any other row.
+    pub unique_rows: usize,
+}
+
+impl GroupStats {
+    /// Stats of `groups`, computed from the `rows` rows of the grouped table so
+    /// they do not depend on whether singletons were dropped.
+    pub fn new(groups: &[Vec<usize>], rows: usize) -> Self {
+        let duplicates = groups.iter().filter(|g| g.len() > 1);
+        let duplicate_groups = duplicates.clone().count();
+        let duplicate_rows = duplicates.map(|g| g.len()).sum();
+        Self {
+            duplicate_groups,
+            duplicate_rows,
+            unique_rows: rows - duplicate_rows,
+        }
+    }
+}
+
+/// The similarity of every row of `groups` to the first row of its group, its
+/// seed. Rows grouped through other rows or by the reviewer may score below
+/// the thresholds.
+pub fn seed_similarities(
+    table: &Table,
+    groups: &[Vec<usize>],
+    config: &GroupingConfig,
+) -> Vec<Vec<f64>> {
+    let matches: Vec<ColumnMatch> = config
+        .column_matches()
+        .into_iter()
+        .map(|m| ColumnMatch {
+            similarity: 0.0,
+            ..m
+        })
+        .collect();
+    let values = |row: usize| -> Vec<String> {
+        matches
+            .iter()
+            .map(|m| config.prepare_key(&table.rows[row][m.col_idx]))
+            .collect()
+    };
+    groups
+        .iter()
+        .map(|group| {
+            let seed = match group.first() {
+                Some(&seed) => values(seed),
+                None => return Vec::new(),
+            };
+            group
+                .iter()
+                .map(|&row| {
+                    let row = values(row);
+                    combined_similarity(
+                        &matches,
+                        config.unit,
+                        0.0,
+                        |i| seed[i].as_str(),
+                        |i| row[i].as_str(),
+                    )
+                    .unwrap_or(0.0)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Levenshtein similarity in percent, lengths and edits counted in graphemes.
+pub fn cal_similarity(left: &str, right: &str) -> f64 {
+    Levenshtein::default().similarity(left, right)
+}
+
+/// The similarity of `left` and `right` if it reaches `similarity`, `None`
+/// otherwise. Cheaper than [`cal_similarity`] as the edit distance is only
+/// computed up to what the threshold allows.
+pub fn cal_similarity_bounded(left: &str, right: &str, similarity: f64) -> Option<f64> {
+    Levenshtein::default().similarity_at_least(left, right, similarity)
+}
+
+/// Greedy grouping by [`cal_similarity`]: every row not grouped yet, in order,
+/// seeds a group with the not yet grouped rows similar to it. The comparisons run on
+/// `threads` threads, `0` for one per core.
+pub fn group_by_similarity_v2(
+    keys: &[String],
+    similarity: f64,
+    case_sensitive: bool,
+    threads: usize,
+) -> Vec<Vec<usize>> {
+    let config = GroupingConfig {
+        similarity,
+        case_sensitive,
+        threads,
+        ..Default::default()
+    };
+    group_keys(keys, &config)
+}
+
+/// Write the rows of `table` group by group, with their row index in front and
+/// an empty row after every group.
+pub fn write_table(csv: &Path, table: &Table, groups: &[Vec<usize>]) -> Result<(), std::io::Error> {
+    write_table_with(csv, table, groups, TextEncoding::Utf8)
+}
+
+/// Same as [`write_table`], in the given text encoding.
+pub fn write_table_with(
+    csv: &Path,
+    table: &Table,
+    groups: &[Vec<usize>],
+    encoding: TextEncoding,
+) -> Result<(), std::io::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(Vec::new());
+    // Add index header to original headers
+    let headers: Vec<String> = vec!["Index".to_string()]
+        .into_iter()
+        .chain(table.headers.iter().cloned())
+        .collect();
+    let cols = headers.len();
+    wtr.write_record(headers)?;
+    for group in groups {
+        for r_idx in group {
+            let mut row = vec![r_idx.to_string()];
+            row.extend(table.rows[*r_idx].iter().cloned());
+            wtr.write_record(row)?;
+        }
+        // Write a empty row
+        wtr.write_record([""].repeat(cols))?;
+    }
+    write_encoded(csv, wtr, encoding)
+}
+
+/// Write the csv held by `wtr` to the file `csv`, in `encoding`.
+pub(crate) fn write_encoded(
+    csv: &Path,
+    wtr: csv::Writer<Vec<u8>>,
+    encoding: TextEncoding,
+) -> Result<(), std::io::Error> {
+    let bytes = wtr.into_inner().map_err(|e| e.into_error())?;
+    // Built from strings, so always valid
+    let text = String::from_utf8(bytes)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    encoding.write(csv, &text)
+}
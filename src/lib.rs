@@ -1,6 +1,52 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
+#[cfg(feature = "gui")]
 mod app;
+#[cfg(feature = "gui")]
 pub use app::TemplateApp;
+mod candidates;
+mod clustering;
+pub use clustering::Clustering;
+mod constraints;
+pub use constraints::{Constraints, RowConstraints};
+mod dialect;
+pub use dialect::{delimiter_name, CsvDialect, DELIMITERS};
 mod edit_distance;
-pub use edit_distance::levenshtein_distance;
+mod encoding;
+mod export;
+pub use edit_distance::{
+    damerau_levenshtein_distance, jaro_winkler_similarity, levenshtein_distance,
+    levenshtein_distance_bounded, ngram_jaccard, token_set_ratio, LengthUnit, Metric,
+    SimilarityMetric, UnitMetric,
+};
+pub use encoding::TextEncoding;
+pub use export::{write_groups, write_json, write_json_lines, write_parquet, ExportFormat};
+pub mod grouping;
+mod linkage;
+mod normalize;
+pub use grouping::{
+    cal_similarity, cal_similarity_bounded, compared_columns, exact_column_keys, exact_keys, group,
+    group_by_similarity_v2, group_columns, group_columns_with, group_keys, preview_table,
+    read_table, read_table_with, read_table_with_progress, seed_similarities, write_table,
+    ColumnMatch, DuplicateKinds, GroupStats, GroupingConfig, Table,
+};
+pub use linkage::{link, link_keys, write_linked, LinkageConfig};
+pub use normalize::Normalization;
+mod phonetic;
+pub use phonetic::{double_metaphone, pinyin, soundex, Phonetic};
+mod progress;
+mod review;
+mod scores;
+pub use progress::Progress;
+pub use review::{GroupStatus, Review, ReviewedGroup};
+pub use scores::{ScoreCache, SCORE_MARGIN};
+mod spreadsheet;
+pub use spreadsheet::{
+    is_spreadsheet, preview_sheet, read_sheet, sheet_names, SheetOptions, SPREADSHEET_EXTENSIONS,
+};
+mod survivorship;
+pub use survivorship::{merge_group, merge_groups, write_merged, MergeConfig, Survivorship};
+mod xlsx;
+pub use xlsx::write_xlsx;
+#[cfg(test)]
+mod testing;
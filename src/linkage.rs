@@ -0,0 +1,218 @@
+//! Record linkage: matching the rows of one table against the rows of
+//! another, like a new supplier file against a master list, instead of
+//! deduplicating a single table.
+
+use std::path::Path;
+
+use crate::candidates::{similar_pairs, Candidates};
+use crate::edit_distance::{Metric, SimilarityMetric};
+use crate::encoding::TextEncoding;
+use crate::grouping::{write_encoded, GroupingConfig, Table};
+use crate::progress::Progress;
+
+/// Which columns of the two tables are matched.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct LinkageConfig {
+    /// The key column of the left table.
+    pub left_column: usize,
+    /// The key column of the right table.
+    pub right_column: usize,
+    /// The most matches kept for every left row, best first, `0` for all of
+    /// them.
+    pub max_matches: usize,
+}
+
+/// For every row of `left`, the rows of `right` whose key reaches the
+/// similarity of `config` with their similarity, best first. Keys are
+/// prepared and compared by the metric of `config`, its match columns are
+/// not used. `None` when `progress` is cancelled before every key is
+/// compared.
+pub fn link(
+    left: &Table,
+    right: &Table,
+    linkage: &LinkageConfig,
+    config: &GroupingConfig,
+    progress: &Progress,
+) -> Option<Vec<Vec<(usize, f64)>>> {
+    link_keys(
+        &left.keys(linkage.left_column),
+        &right.keys(linkage.right_column),
+        linkage.max_matches,
+        config,
+        progress,
+    )
+}
+
+/// Same as [`link`], for the already extracted key columns, keeping
+/// `max_matches` matches per left key, `0` for all of them.
+pub fn link_keys(
+    left: &[String],
+    right: &[String],
+    max_matches: usize,
+    config: &GroupingConfig,
+    progress: &Progress,
+) -> Option<Vec<Vec<(usize, f64)>>> {
+    // Both tables are indexed together, only pairs across them are candidates
+    let keys: Vec<String> = left
+        .iter()
+        .chain(right)
+        .map(|key| config.prepare_key(key))
+        .collect();
+    let rows = left.len();
+    let candidates = match config.metric {
+        Metric::Levenshtein => Candidates::new(&keys, config.similarity, config.unit),
+        _ => Candidates::all(keys.len()),
+    }
+    .across(rows);
+    let metric = config.metric.in_unit(config.unit);
+    let similarity =
+        |a: usize, b: usize| metric.similarity_at_least(&keys[a], &keys[b], config.similarity);
+    let neighbours = similar_pairs(&candidates, similarity, config.threads, progress)?;
+
+    Some(
+        neighbours
+            .into_iter()
+            .take(rows)
+            .map(|mut matches| {
+                for m in &mut matches {
+                    m.0 -= rows;
+                }
+                // Stable, so matches of the same similarity keep the right
+                // table order
+                matches.sort_by(|a, b| b.1.total_cmp(&a.1));
+                if max_matches > 0 {
+                    matches.truncate(max_matches);
+                }
+                matches
+            })
+            .collect(),
+    )
+}
+
+/// Write every row of `left` joined with each of its matches in `right`, the
+/// similarity between them, in `encoding`. Rows without a match are written
+/// once with empty right values. Right columns named like a left column get
+/// ` (right)` appended.
+pub fn write_linked(
+    csv: &Path,
+    left: &Table,
+    right: &Table,
+    matches: &[Vec<(usize, f64)>],
+    encoding: TextEncoding,
+) -> Result<(), std::io::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(Vec::new());
+    let right_headers = right.headers.iter().map(|h| {
+        if left.headers.contains(h) {
+            format!("{} (right)", h)
+        } else {
+            h.clone()
+        }
+    });
+    let headers: Vec<String> = left
+        .headers
+        .iter()
+        .cloned()
+        .chain(std::iter::once(String::from("Similarity")))
+        .chain(right_headers)
+        .collect();
+    wtr.write_record(&headers)?;
+
+    let no_match = vec![String::new(); right.headers.len()];
+    for (row, row_matches) in left.rows.iter().zip(matches) {
+        if row_matches.is_empty() {
+            wtr.write_record(
+                row.iter()
+                    .chain(std::iter::once(&String::new()))
+                    .chain(&no_match),
+            )?;
+        }
+        for &(right_row, similarity) in row_matches {
+            let similarity = format!("{:.2}", similarity);
+            wtr.write_record(
+                row.iter()
+                    .chain(std::iter::once(&similarity))
+                    .chain(&right.rows[right_row]),
+            )?;
+        }
+    }
+    write_encoded(csv, wtr, encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_distance::LengthUnit;
+    use crate::testing::Lcg;
+
+    fn keys(rng: &mut Lcg, len: usize) -> Vec<String> {
+        let alphabet = ["a", "B", "b", "é", "中", " "];
+        (0..len)
+            .map(|_| {
+                (0..rng.below(8))
+                    .map(|_| alphabet[rng.below(alphabet.len())])
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn link_matches_comparing_every_pair_across() {
+        let mut rng = Lcg::new(0x5851_f42d_4c95_7f2d);
+        let left = keys(&mut rng, 80);
+        let mut right = keys(&mut rng, 40);
+        // Near copies, so that high thresholds have matches to find
+        for key in &left[..20] {
+            right.push(format!("{}{}", key, ["a", "b"][rng.below(2)]));
+        }
+        for metric in Metric::ALL {
+            for unit in LengthUnit::ALL {
+                for similarity in [0.0, 50.0, 66.67, 80.0, 100.0] {
+                    let config = GroupingConfig {
+                        metric,
+                        unit,
+                        similarity,
+                        case_sensitive: false,
+                        ..Default::default()
+                    };
+                    let scored = metric.in_unit(unit);
+                    let prepared: Vec<String> =
+                        right.iter().map(|r| config.prepare_key(r)).collect();
+                    let expected: Vec<Vec<(usize, f64)>> = left
+                        .iter()
+                        .map(|l| {
+                            let l = config.prepare_key(l);
+                            let mut matches: Vec<(usize, f64)> = prepared
+                                .iter()
+                                .enumerate()
+                                .filter_map(|(j, r)| {
+                                    Some(j).zip(scored.similarity_at_least(&l, r, similarity))
+                                })
+                                .collect();
+                            matches.sort_by(|a, b| b.1.total_cmp(&a.1));
+                            matches
+                        })
+                        .collect();
+
+                    let progress = Progress::new();
+                    let linked = link_keys(&left, &right, 0, &config, &progress);
+                    assert_eq!(
+                        linked.as_ref(),
+                        Some(&expected),
+                        "{:?} in {:?} at {}",
+                        metric,
+                        unit,
+                        similarity
+                    );
+                    assert_eq!(progress.done(), progress.total());
+                    assert!(progress.total() <= left.len() * right.len());
+                    if metric != Metric::Levenshtein {
+                        assert_eq!(progress.total(), left.len() * right.len());
+                    }
+                }
+            }
+        }
+    }
+}
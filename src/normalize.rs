@@ -0,0 +1,118 @@
+//! Normalization of the keys before they are compared.
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Steps applied to every key before comparison, in the order of the fields.
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Normalization {
+    /// Unicode compatibility composition, e.g. `ﬁ` to `fi` and `①` to `1`.
+    pub nfkc: bool,
+    /// Full-width ASCII variants and the ideographic space, common in CJK
+    /// input, to their half-width forms.
+    pub full_width_to_half_width: bool,
+    /// Remove accents and other diacritics, `é` to `e`.
+    pub strip_accents: bool,
+    /// Replace punctuation by spaces.
+    pub remove_punctuation: bool,
+    /// Words removed from the keys, e.g. "Ltd" or "有限公司". Matching ignores
+    /// ASCII case and skips occurrences inside a longer latin word. Blank
+    /// entries are ignored.
+    pub stop_words: Vec<String>,
+    /// Trim the key and turn every run of whitespace into a single space.
+    pub collapse_whitespace: bool,
+}
+
+impl Normalization {
+    /// Whether no step is enabled, keys are compared as they are.
+    pub fn is_identity(&self) -> bool {
+        !self.nfkc
+            && !self.full_width_to_half_width
+            && !self.strip_accents
+            && !self.remove_punctuation
+            && self.stop_words.iter().all(|w| w.trim().is_empty())
+            && !self.collapse_whitespace
+    }
+
+    pub fn apply(&self, key: &str) -> String {
+        let mut key = key.to_owned();
+        if self.nfkc {
+            key = key.nfkc().collect();
+        }
+        if self.full_width_to_half_width {
+            key = key.chars().map(to_half_width).collect();
+        }
+        if self.strip_accents {
+            key = key.nfd().filter(|c| !is_combining_mark(*c)).nfc().collect();
+        }
+        if self.remove_punctuation {
+            key = key
+                .chars()
+                .map(|c| if is_punctuation(c) { ' ' } else { c })
+                .collect();
+        }
+        for word in &self.stop_words {
+            let word = word.trim();
+            if !word.is_empty() {
+                key = remove_word(&key, word);
+            }
+        }
+        if self.collapse_whitespace {
+            key = key.split_whitespace().collect::<Vec<&str>>().join(" ");
+        }
+        key
+    }
+}
+
+fn to_half_width(c: char) -> char {
+    match c {
+        '\u{3000}' => ' ',
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation()
+        || matches!(c,
+            // Latin-1 punctuation
+            '¡' | '§' | '«' | '¶' | '·' | '»' | '¿'
+            // General punctuation: dashes, quotes, ellipsis, ...
+            | '\u{2010}'..='\u{2027}'
+            | '\u{2030}'..='\u{205E}'
+            // CJK punctuation: 、。〃 and brackets
+            | '\u{3001}'..='\u{3003}'
+            | '\u{3008}'..='\u{3011}'
+            | '\u{3014}'..='\u{301F}'
+            // Full-width punctuation
+            | '\u{FF01}'..='\u{FF0F}'
+            | '\u{FF1A}'..='\u{FF20}'
+            | '\u{FF3B}'..='\u{FF40}'
+            | '\u{FF5B}'..='\u{FF65}')
+}
+
+/// Remove every occurrence of `word` from `key` that is not part of a longer
+/// latin word.
+fn remove_word(key: &str, word: &str) -> String {
+    let is_latin = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+    let mut out = String::with_capacity(key.len());
+    let mut i = 0;
+    while i < key.len() {
+        let end = i + word.len();
+        if key.is_char_boundary(end)
+            && key
+                .get(i..end)
+                .is_some_and(|s| s.eq_ignore_ascii_case(word))
+            && !(is_latin(word.chars().next()) && is_latin(key[..i].chars().next_back()))
+            && !(is_latin(word.chars().next_back()) && is_latin(key[end..].chars().next()))
+        {
+            i = end;
+            continue;
+        }
+        let c = key[i..].chars().next().expect("i is a char boundary");
+        out.push(c);
+        i += c.len_utf8();
+    }
+    out
+}
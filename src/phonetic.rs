@@ -0,0 +1,794 @@
+//! Phonetic keys, so names written differently but pronounced alike compare
+//! as equal: pinyin for Han characters, Soundex and Double Metaphone for
+//! latin words.
+
+use deunicode::deunicode_char;
+
+/// How the keys are turned into phonetic keys before comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum Phonetic {
+    /// Keys are compared as they are.
+    #[default]
+    Off,
+    /// Han characters are replaced by their pinyin, "张伟" becomes "Zhang Wei".
+    Pinyin,
+    /// Pinyin, then every word is replaced by its Soundex code.
+    Soundex,
+    /// Pinyin, then every word is replaced by its primary Double Metaphone code.
+    DoubleMetaphone,
+}
+
+impl Phonetic {
+    pub const ALL: [Phonetic; 4] = [
+        Phonetic::Off,
+        Phonetic::Pinyin,
+        Phonetic::Soundex,
+        Phonetic::DoubleMetaphone,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phonetic::Off => "Off",
+            Phonetic::Pinyin => "Pinyin",
+            Phonetic::Soundex => "Soundex",
+            Phonetic::DoubleMetaphone => "Double Metaphone",
+        }
+    }
+
+    /// The phonetic key of `s`.
+    pub fn key(&self, s: &str) -> String {
+        match self {
+            Phonetic::Off => s.to_owned(),
+            Phonetic::Pinyin => pinyin(s),
+            Phonetic::Soundex => encode_words(&pinyin(s), soundex),
+            Phonetic::DoubleMetaphone => encode_words(&pinyin(s), |w| double_metaphone(w).0),
+        }
+    }
+}
+
+/// Replace every word by its code, keeping the words without one.
+fn encode_words(s: &str, encode: impl Fn(&str) -> String) -> String {
+    s.split_whitespace()
+        .map(|word| {
+            let code = encode(word);
+            if code.is_empty() {
+                word.to_owned()
+            } else {
+                code
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn is_han(c: char) -> bool {
+    matches!(c,
+        '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{2EBEF}')
+}
+
+/// Replace the Han characters of `s` by their toneless pinyin, one capitalized
+/// word per character. Other text is kept, `s` is returned as is without Han
+/// characters.
+pub fn pinyin(s: &str) -> String {
+    if !s.chars().any(is_han) {
+        return s.to_owned();
+    }
+    let mut out = String::with_capacity(s.len() * 2);
+    for c in s.chars() {
+        match deunicode_char(c).filter(|_| is_han(c)) {
+            Some(syllable) => {
+                out.push(' ');
+                out.push_str(syllable.trim());
+                out.push(' ');
+            }
+            None => out.push(c),
+        }
+    }
+    out.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// American Soundex code of the ASCII letters of `word`, e.g. `R163` for
+/// "Robert" and "Rupert". Empty when `word` has no ASCII letter.
+pub fn soundex(word: &str) -> String {
+    fn digit(c: char) -> Option<char> {
+        match c {
+            'B' | 'F' | 'P' | 'V' => Some('1'),
+            'C' | 'G' | 'J' | 'K' | 'Q' | 'S' | 'X' | 'Z' => Some('2'),
+            'D' | 'T' => Some('3'),
+            'L' => Some('4'),
+            'M' | 'N' => Some('5'),
+            'R' => Some('6'),
+            _ => None,
+        }
+    }
+
+    let mut letters = word
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_uppercase());
+    let first = match letters.next() {
+        Some(first) => first,
+        None => return String::new(),
+    };
+    let mut code = String::from(first);
+    let mut last = digit(first);
+    for c in letters {
+        let d = digit(c);
+        if d.is_some() && d != last {
+            code.extend(d);
+            if code.len() == 4 {
+                break;
+            }
+        }
+        // H and W do not separate letters with the same code, vowels do
+        if c != 'H' && c != 'W' {
+            last = d;
+        }
+    }
+    while code.len() < 4 {
+        code.push('0');
+    }
+    code
+}
+
+/// Primary and alternate Double Metaphone codes of `word`, at most 4 long
+/// each. Both are empty when `word` has no letter. Names of several words,
+/// like "Von Thalberg", are encoded as one.
+pub fn double_metaphone(word: &str) -> (String, String) {
+    let mut encoder = Metaphone::new(word);
+    encoder.encode();
+    encoder.primary.truncate(MAX_CODE);
+    encoder.alternate.truncate(MAX_CODE);
+    (encoder.primary, encoder.alternate)
+}
+
+const MAX_CODE: usize = 4;
+
+/// State of a Double Metaphone encoding, following Lawrence Philips' rules.
+struct Metaphone {
+    value: Vec<char>,
+    primary: String,
+    alternate: String,
+    slavo_germanic: bool,
+}
+
+impl Metaphone {
+    fn new(word: &str) -> Self {
+        // Words stay apart by a single space, so rules like the germanic
+        // "VAN " and "VON " prefixes see where a word ends
+        let letters: String = word
+            .chars()
+            .filter(|c| c.is_alphabetic() || c.is_whitespace())
+            .flat_map(char::to_uppercase)
+            .collect();
+        let value: Vec<char> = letters
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .chars()
+            .collect();
+        let text: String = value.iter().collect();
+        let slavo_germanic = text.contains('W')
+            || text.contains('K')
+            || text.contains("CZ")
+            || text.contains("WITZ");
+        Self {
+            value,
+            primary: String::new(),
+            alternate: String::new(),
+            slavo_germanic,
+        }
+    }
+
+    fn len(&self) -> isize {
+        self.value.len() as isize
+    }
+
+    /// The letter at `i`, `'\0'` outside of the word.
+    fn at(&self, i: isize) -> char {
+        if i < 0 {
+            return '\0';
+        }
+        self.value.get(i as usize).copied().unwrap_or('\0')
+    }
+
+    /// Whether the `len` letters from `start` are one of `options`.
+    fn contains(&self, start: isize, len: usize, options: &[&str]) -> bool {
+        if start < 0 || start as usize + len > self.value.len() {
+            return false;
+        }
+        let part: String = self.value[start as usize..start as usize + len]
+            .iter()
+            .collect();
+        options.contains(&part.as_str())
+    }
+
+    fn is_vowel(c: char) -> bool {
+        matches!(c, 'A' | 'E' | 'I' | 'O' | 'U' | 'Y')
+    }
+
+    fn add(&mut self, primary: &str, alternate: &str) {
+        self.primary.push_str(primary);
+        self.alternate.push_str(alternate);
+    }
+
+    fn add_both(&mut self, code: &str) {
+        self.add(code, code);
+    }
+
+    fn is_complete(&self) -> bool {
+        self.primary.len() >= MAX_CODE && self.alternate.len() >= MAX_CODE
+    }
+
+    /// Skip a doubled `c`.
+    fn next_unless_double(&self, i: isize, c: char) -> isize {
+        if self.at(i + 1) == c {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn encode(&mut self) {
+        // Silent first letter
+        let mut i = if self.contains(0, 2, &["GN", "KN", "PN", "WR", "PS"]) {
+            1
+        } else {
+            0
+        };
+        while !self.is_complete() && i < self.len() {
+            i = match self.at(i) {
+                'A' | 'E' | 'I' | 'O' | 'U' | 'Y' => {
+                    if i == 0 {
+                        self.add_both("A");
+                    }
+                    i + 1
+                }
+                'B' => {
+                    self.add_both("P");
+                    self.next_unless_double(i, 'B')
+                }
+                'Ç' => {
+                    self.add_both("S");
+                    i + 1
+                }
+                'C' => self.c(i),
+                'D' => self.d(i),
+                'F' => {
+                    self.add_both("F");
+                    self.next_unless_double(i, 'F')
+                }
+                'G' => self.g(i),
+                'H' => self.h(i),
+                'J' => self.j(i),
+                'K' => {
+                    self.add_both("K");
+                    self.next_unless_double(i, 'K')
+                }
+                'L' => self.l(i),
+                'M' => {
+                    self.add_both("M");
+                    let umb = self.contains(i - 1, 3, &["UMB"])
+                        && (i + 1 == self.len() - 1 || self.contains(i + 2, 2, &["ER"]));
+                    if self.at(i + 1) == 'M' || umb {
+                        i + 2
+                    } else {
+                        i + 1
+                    }
+                }
+                'N' => {
+                    self.add_both("N");
+                    self.next_unless_double(i, 'N')
+                }
+                'Ñ' => {
+                    self.add_both("N");
+                    i + 1
+                }
+                'P' => self.p(i),
+                'Q' => {
+                    self.add_both("K");
+                    self.next_unless_double(i, 'Q')
+                }
+                'R' => self.r(i),
+                'S' => self.s(i),
+                'T' => self.t(i),
+                'V' => {
+                    self.add_both("F");
+                    self.next_unless_double(i, 'V')
+                }
+                'W' => self.w(i),
+                'X' => self.x(i),
+                'Z' => self.z(i),
+                _ => i + 1,
+            };
+        }
+    }
+
+    fn c(&mut self, i: isize) -> isize {
+        if self.c_is_k(i) {
+            self.add_both("K");
+            i + 2
+        } else if i == 0 && self.contains(i, 6, &["CAESAR"]) {
+            self.add_both("S");
+            i + 2
+        } else if self.contains(i, 2, &["CH"]) {
+            self.ch(i)
+        } else if self.contains(i, 2, &["CZ"]) && !self.contains(i - 2, 4, &["WICZ"]) {
+            self.add("S", "X");
+            i + 2
+        } else if self.contains(i + 1, 3, &["CIA"]) {
+            self.add_both("X");
+            i + 3
+        } else if self.contains(i, 2, &["CC"]) && !(i == 1 && self.at(0) == 'M') {
+            self.cc(i)
+        } else if self.contains(i, 2, &["CK", "CG", "CQ"]) {
+            self.add_both("K");
+            i + 2
+        } else if self.contains(i, 2, &["CI", "CE", "CY"]) {
+            if self.contains(i, 3, &["CIO", "CIE", "CIA"]) {
+                self.add("S", "X");
+            } else {
+                self.add_both("S");
+            }
+            i + 2
+        } else {
+            self.add_both("K");
+            if self.contains(i + 1, 2, &[" C", " Q", " G"]) {
+                i + 3
+            } else if self.contains(i + 1, 1, &["C", "K", "Q"])
+                && !self.contains(i + 1, 2, &["CE", "CI"])
+            {
+                i + 2
+            } else {
+                i + 1
+            }
+        }
+    }
+
+    /// Germanic "ACH" as in "Bacher", and "CHIA".
+    fn c_is_k(&self, i: isize) -> bool {
+        if self.contains(i, 4, &["CHIA"]) {
+            return true;
+        }
+        if i <= 1 || Self::is_vowel(self.at(i - 2)) || !self.contains(i - 1, 3, &["ACH"]) {
+            return false;
+        }
+        let c = self.at(i + 2);
+        (c != 'I' && c != 'E') || self.contains(i - 2, 6, &["BACHER", "MACHER"])
+    }
+
+    fn cc(&mut self, i: isize) -> isize {
+        if self.contains(i + 2, 1, &["I", "E", "H"]) && !self.contains(i + 2, 2, &["HU"]) {
+            if (i == 1 && self.at(i - 1) == 'A') || self.contains(i - 1, 5, &["UCCEE", "UCCES"]) {
+                self.add_both("KS");
+            } else {
+                self.add_both("X");
+            }
+            i + 3
+        } else {
+            self.add_both("K");
+            i + 2
+        }
+    }
+
+    fn ch(&mut self, i: isize) -> isize {
+        if i > 0 && self.contains(i, 4, &["CHAE"]) {
+            self.add("K", "X");
+        } else if self.ch_is_greek(i) || self.ch_is_k(i) {
+            self.add_both("K");
+        } else if i > 0 {
+            if self.contains(0, 2, &["MC"]) {
+                self.add_both("K");
+            } else {
+                self.add("X", "K");
+            }
+        } else {
+            self.add_both("X");
+        }
+        i + 2
+    }
+
+    fn ch_is_greek(&self, i: isize) -> bool {
+        i == 0
+            && (self.contains(i + 1, 5, &["HARAC", "HARIS"])
+                || self.contains(i + 1, 3, &["HOR", "HYM", "HIA", "HEM"]))
+            && !self.contains(0, 5, &["CHORE"])
+    }
+
+    fn ch_is_k(&self, i: isize) -> bool {
+        self.contains(0, 4, &["VAN ", "VON "])
+            || self.contains(0, 3, &["SCH"])
+            || self.contains(i - 2, 6, &["ORCHES", "ARCHIT", "ORCHID"])
+            || self.contains(i + 2, 1, &["T", "S"])
+            || ((self.contains(i - 1, 1, &["A", "O", "U", "E"]) || i == 0)
+                && (self.contains(
+                    i + 2,
+                    1,
+                    &["L", "R", "N", "M", "B", "H", "F", "V", "W", " "],
+                ) || i + 1 == self.len() - 1))
+    }
+
+    fn d(&mut self, i: isize) -> isize {
+        if self.contains(i, 2, &["DG"]) {
+            if self.contains(i + 2, 1, &["I", "E", "Y"]) {
+                self.add_both("J");
+                i + 3
+            } else {
+                self.add_both("TK");
+                i + 2
+            }
+        } else if self.contains(i, 2, &["DT", "DD"]) {
+            self.add_both("T");
+            i + 2
+        } else {
+            self.add_both("T");
+            i + 1
+        }
+    }
+
+    fn g(&mut self, i: isize) -> isize {
+        if self.at(i + 1) == 'H' {
+            self.gh(i)
+        } else if self.at(i + 1) == 'N' {
+            if i == 1 && Self::is_vowel(self.at(0)) && !self.slavo_germanic {
+                self.add("KN", "N");
+            } else if !self.contains(i + 2, 2, &["EY"])
+                && self.at(i + 1) != 'Y'
+                && !self.slavo_germanic
+            {
+                self.add("N", "KN");
+            } else {
+                self.add_both("KN");
+            }
+            i + 2
+        } else if self.contains(i + 1, 2, &["LI"]) && !self.slavo_germanic {
+            self.add("KL", "L");
+            i + 2
+        } else if self.g_is_kj(i) {
+            self.add("K", "J");
+            i + 2
+        } else if self.contains(i + 1, 1, &["E", "I", "Y"])
+            || self.contains(i - 1, 4, &["AGGI", "OGGI"])
+        {
+            if self.contains(0, 4, &["VAN ", "VON "])
+                || self.contains(0, 3, &["SCH"])
+                || self.contains(i + 1, 2, &["ET"])
+            {
+                self.add_both("K");
+            } else if self.contains(i + 1, 3, &["IER"]) {
+                self.add_both("J");
+            } else {
+                self.add("J", "K");
+            }
+            i + 2
+        } else {
+            self.add_both("K");
+            self.next_unless_double(i, 'G')
+        }
+    }
+
+    /// "GE", "GI" and "GY" that are hard in the primary and soft in the
+    /// alternate code, as in "Gerald" or "Biaggi".
+    fn g_is_kj(&self, i: isize) -> bool {
+        let start = [
+            "ES", "EP", "EB", "EL", "EY", "IB", "IL", "IN", "IE", "EI", "ER",
+        ];
+        (i == 0 && (self.at(i + 1) == 'Y' || self.contains(i + 1, 2, &start)))
+            || ((self.contains(i + 1, 2, &["ER"]) || self.at(i + 1) == 'Y')
+                && !self.contains(0, 6, &["DANGER", "RANGER", "MANGER"])
+                && !self.contains(i - 1, 1, &["E", "I"])
+                && !self.contains(i - 1, 3, &["RGY", "OGY"]))
+    }
+
+    fn gh(&mut self, i: isize) -> isize {
+        if i > 0 && !Self::is_vowel(self.at(i - 1)) {
+            self.add_both("K");
+        } else if i == 0 {
+            if self.at(i + 2) == 'I' {
+                self.add_both("J");
+            } else {
+                self.add_both("K");
+            }
+        } else if (i > 1 && self.contains(i - 2, 1, &["B", "H", "D"]))
+            || (i > 2 && self.contains(i - 3, 1, &["B", "H", "D"]))
+            || (i > 3 && self.contains(i - 4, 1, &["B", "H"]))
+        {
+            // Silent, as in "Hugh" or "bough"
+        } else if i > 2
+            && self.at(i - 1) == 'U'
+            && self.contains(i - 3, 1, &["C", "G", "L", "R", "T"])
+        {
+            self.add_both("F");
+        } else if i > 0 && self.at(i - 1) != 'I' {
+            self.add_both("K");
+        }
+        i + 2
+    }
+
+    fn h(&mut self, i: isize) -> isize {
+        // Only kept between vowels or at the start before a vowel
+        if (i == 0 || Self::is_vowel(self.at(i - 1))) && Self::is_vowel(self.at(i + 1)) {
+            self.add_both("H");
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn j(&mut self, i: isize) -> isize {
+        if self.contains(i, 4, &["JOSE"]) || self.contains(0, 4, &["SAN "]) {
+            if (i == 0 && self.at(i + 4) == ' ')
+                || self.len() == 4
+                || self.contains(0, 4, &["SAN "])
+            {
+                self.add_both("H");
+            } else {
+                self.add("J", "H");
+            }
+            return i + 1;
+        }
+        if i == 0 {
+            self.add("J", "A");
+        } else if Self::is_vowel(self.at(i - 1))
+            && !self.slavo_germanic
+            && (self.at(i + 1) == 'A' || self.at(i + 1) == 'O')
+        {
+            self.add("J", "H");
+        } else if i == self.len() - 1 {
+            self.add("J", "");
+        } else if !self.contains(i + 1, 1, &["L", "T", "K", "S", "N", "M", "B", "Z"])
+            && !self.contains(i - 1, 1, &["S", "K", "L"])
+        {
+            self.add_both("J");
+        }
+        self.next_unless_double(i, 'J')
+    }
+
+    fn l(&mut self, i: isize) -> isize {
+        if self.at(i + 1) != 'L' {
+            self.add_both("L");
+            return i + 1;
+        }
+        // Spanish "-illo", "-alla"
+        let len = self.len();
+        let spanish = (i == len - 3 && self.contains(i - 1, 4, &["ILLO", "ILLA", "ALLE"]))
+            || ((self.contains(len - 2, 2, &["AS", "OS"])
+                || self.contains(len - 1, 1, &["A", "O"]))
+                && self.contains(i - 1, 4, &["ALLE"]));
+        if spanish {
+            self.add("L", "");
+        } else {
+            self.add_both("L");
+        }
+        i + 2
+    }
+
+    fn p(&mut self, i: isize) -> isize {
+        if self.at(i + 1) == 'H' {
+            self.add_both("F");
+            return i + 2;
+        }
+        self.add_both("P");
+        if self.contains(i + 1, 1, &["P", "B"]) {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn r(&mut self, i: isize) -> isize {
+        // French final "-ier"
+        if i == self.len() - 1
+            && !self.slavo_germanic
+            && self.contains(i - 2, 2, &["IE"])
+            && !self.contains(i - 4, 2, &["ME", "MA"])
+        {
+            self.add("", "R");
+        } else {
+            self.add_both("R");
+        }
+        self.next_unless_double(i, 'R')
+    }
+
+    fn s(&mut self, i: isize) -> isize {
+        if self.contains(i - 1, 3, &["ISL", "YSL"]) {
+            // Silent, as in "island"
+            i + 1
+        } else if i == 0 && self.contains(i, 5, &["SUGAR"]) {
+            self.add("X", "S");
+            i + 1
+        } else if self.contains(i, 2, &["SH"]) {
+            if self.contains(i + 1, 4, &["HEIM", "HOEK", "HOLM", "HOLZ"]) {
+                self.add_both("S");
+            } else {
+                self.add_both("X");
+            }
+            i + 2
+        } else if self.contains(i, 3, &["SIO", "SIA"]) || self.contains(i, 4, &["SIAN"]) {
+            if self.slavo_germanic {
+                self.add_both("S");
+            } else {
+                self.add("S", "X");
+            }
+            i + 3
+        } else if (i == 0 && self.contains(i + 1, 1, &["M", "N", "L", "W"]))
+            || self.contains(i + 1, 1, &["Z"])
+        {
+            self.add("S", "X");
+            self.next_unless_double(i, 'Z')
+        } else if self.contains(i, 2, &["SC"]) {
+            self.sc(i)
+        } else {
+            // French final "-ais", "-ois"
+            if i == self.len() - 1 && self.contains(i - 2, 2, &["AI", "OI"]) {
+                self.add("", "S");
+            } else {
+                self.add_both("S");
+            }
+            if self.contains(i + 1, 1, &["S", "Z"]) {
+                i + 2
+            } else {
+                i + 1
+            }
+        }
+    }
+
+    fn sc(&mut self, i: isize) -> isize {
+        if self.at(i + 2) == 'H' {
+            if self.contains(i + 3, 2, &["OO", "ER", "EN", "UY", "ED", "EM"]) {
+                if self.contains(i + 3, 2, &["ER", "EN"]) {
+                    self.add("X", "SK");
+                } else {
+                    self.add_both("SK");
+                }
+            } else if i == 0 && !Self::is_vowel(self.at(3)) && self.at(3) != 'W' {
+                self.add("X", "S");
+            } else {
+                self.add_both("X");
+            }
+        } else if self.contains(i + 2, 1, &["I", "E", "Y"]) {
+            self.add_both("S");
+        } else {
+            self.add_both("SK");
+        }
+        i + 3
+    }
+
+    fn t(&mut self, i: isize) -> isize {
+        if self.contains(i, 4, &["TION"]) || self.contains(i, 3, &["TIA", "TCH"]) {
+            self.add_both("X");
+            i + 3
+        } else if self.contains(i, 2, &["TH"]) || self.contains(i, 3, &["TTH"]) {
+            if self.contains(i + 2, 2, &["OM", "AM"])
+                || self.contains(0, 4, &["VAN ", "VON "])
+                || self.contains(0, 3, &["SCH"])
+            {
+                self.add_both("T");
+            } else {
+                self.add("0", "T");
+            }
+            i + 2
+        } else {
+            self.add_both("T");
+            if self.contains(i + 1, 1, &["T", "D"]) {
+                i + 2
+            } else {
+                i + 1
+            }
+        }
+    }
+
+    fn w(&mut self, i: isize) -> isize {
+        if self.contains(i, 2, &["WR"]) {
+            self.add_both("R");
+            return i + 2;
+        }
+        if i == 0 && (Self::is_vowel(self.at(i + 1)) || self.contains(i, 2, &["WH"])) {
+            if Self::is_vowel(self.at(i + 1)) {
+                self.add("A", "F");
+            } else {
+                self.add_both("A");
+            }
+            i + 1
+        } else if (i == self.len() - 1 && Self::is_vowel(self.at(i - 1)))
+            || self.contains(i - 1, 5, &["EWSKI", "EWSKY", "OWSKI", "OWSKY"])
+            || self.contains(0, 3, &["SCH"])
+        {
+            self.add("", "F");
+            i + 1
+        } else if self.contains(i, 4, &["WICZ", "WITZ"]) {
+            self.add("TS", "FX");
+            i + 4
+        } else {
+            i + 1
+        }
+    }
+
+    fn x(&mut self, i: isize) -> isize {
+        if i == 0 {
+            self.add_both("S");
+            return i + 1;
+        }
+        // French final "-eaux", "-oux"
+        let silent = i == self.len() - 1
+            && (self.contains(i - 3, 3, &["IAU", "EAU"]) || self.contains(i - 2, 2, &["AU", "OU"]));
+        if !silent {
+            self.add_both("KS");
+        }
+        if self.contains(i + 1, 1, &["C", "X"]) {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn z(&mut self, i: isize) -> isize {
+        if self.at(i + 1) == 'H' {
+            // Chinese pinyin, as in "Zhao"
+            self.add_both("J");
+            return i + 2;
+        }
+        if self.contains(i + 1, 2, &["ZO", "ZI", "ZA"])
+            || (self.slavo_germanic && i > 0 && self.at(i - 1) != 'T')
+        {
+            self.add("S", "TS");
+        } else {
+            self.add_both("S");
+        }
+        self.next_unless_double(i, 'Z')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(primary: &str, alternate: &str) -> (String, String) {
+        (primary.to_owned(), alternate.to_owned())
+    }
+
+    #[test]
+    fn double_metaphone_reference_codes() {
+        assert_eq!(double_metaphone("Smith"), codes("SM0", "XMT"));
+        assert_eq!(double_metaphone("Schmidt"), codes("XMT", "SMT"));
+        assert_eq!(double_metaphone("Jankelowicz"), codes("JNKL", "ANKL"));
+        assert_eq!(double_metaphone("Caesar"), codes("SSR", "SSR"));
+        assert_eq!(double_metaphone("Knight"), codes("NT", "NT"));
+        assert_eq!(double_metaphone("Dumb"), codes("TM", "TM"));
+        assert_eq!(double_metaphone("Jose"), codes("HS", "HS"));
+        assert_eq!(double_metaphone("Xavier"), codes("SF", "SFR"));
+        assert_eq!(double_metaphone("Orchestra"), codes("ARKS", "ARKS"));
+        assert_eq!(double_metaphone(""), codes("", ""));
+    }
+
+    #[test]
+    fn double_metaphone_germanic_prefixes() {
+        // "TH" is "0" except after a germanic prefix
+        assert_eq!(double_metaphone("Thalberg"), codes("0LPR", "TLPR"));
+        assert_eq!(double_metaphone("Von Thalberg"), codes("FNTL", "FNTL"));
+        assert_eq!(double_metaphone("  von   thalberg "), codes("FNTL", "FNTL"));
+    }
+
+    #[test]
+    fn soundex_reference_codes() {
+        assert_eq!(soundex("Robert"), "R163");
+        assert_eq!(soundex("Rupert"), "R163");
+        assert_eq!(soundex("Rubin"), "R150");
+        assert_eq!(soundex("Ashcraft"), "A261");
+        assert_eq!(soundex("Tymczak"), "T522");
+        assert_eq!(soundex("Pfister"), "P236");
+        assert_eq!(soundex("Lee"), "L000");
+        assert_eq!(soundex("张"), "");
+    }
+
+    #[test]
+    fn pinyin_of_han_characters() {
+        assert_eq!(pinyin("张伟"), "Zhang Wei");
+        assert_eq!(pinyin("张伟 Ltd"), "Zhang Wei Ltd");
+        assert_eq!(pinyin("Smith"), "Smith");
+        assert_eq!(Phonetic::Soundex.key("张伟"), "Z520 W000");
+    }
+}
@@ -0,0 +1,105 @@
+//! Progress of work running on other threads, like loading or grouping a
+//! table, and its cancellation.
+
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Shared between the threads doing the work, which report the steps they
+/// are done with and stop once cancelled, and the thread watching them.
+#[derive(Debug)]
+pub struct Progress {
+    started: Instant,
+    done: AtomicUsize,
+    total: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            done: AtomicUsize::new(0),
+            total: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    /// The work has `total` steps.
+    pub(crate) fn set_total(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    /// Count `steps` more steps as done.
+    pub(crate) fn advance(&self, steps: usize) {
+        self.done.fetch_add(steps, Ordering::Relaxed);
+    }
+
+    /// The steps done so far.
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    /// The steps to do, `0` until the work is counted.
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// The part of the steps done, `0.0..=1.0`.
+    pub fn fraction(&self) -> f32 {
+        match self.total() {
+            0 => 0.0,
+            total => (self.done() as f32 / total as f32).min(1.0),
+        }
+    }
+
+    /// The time left at the pace so far, once any step is done.
+    pub fn eta(&self) -> Option<Duration> {
+        let (done, total) = (self.done(), self.total());
+        if done == 0 || total == 0 {
+            return None;
+        }
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let left = elapsed / done as f64 * total.saturating_sub(done) as f64;
+        Some(Duration::from_secs_f64(left))
+    }
+
+    /// Ask the work to stop, it does so at its next check.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts the bytes read from a reader in a [`Progress`], and fails reading
+/// once it is cancelled.
+pub(crate) struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub(crate) fn new(inner: R, progress: &'a Progress) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.progress.is_cancelled() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, "Cancelled"));
+        }
+        let read = self.inner.read(buf)?;
+        self.progress.advance(read);
+        Ok(read)
+    }
+}
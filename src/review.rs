@@ -0,0 +1,257 @@
+//! Manual review of a grouping result: confirming groups, splitting and
+//! merging them, with undo.
+
+use std::collections::HashMap;
+
+use crate::constraints::RowConstraints;
+
+/// What the reviewer decided about a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum GroupStatus {
+    /// As found by the grouping, not looked at yet.
+    #[default]
+    Unreviewed,
+    /// The rows are duplicates of each other.
+    Confirmed,
+    /// The row was split off a group it did not belong to.
+    NotDuplicate,
+}
+
+impl GroupStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupStatus::Unreviewed => "Unreviewed",
+            GroupStatus::Confirmed => "Confirmed",
+            GroupStatus::NotDuplicate => "Not duplicate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReviewedGroup {
+    pub rows: Vec<usize>,
+    pub status: GroupStatus,
+}
+
+fn sorted(rows: &[usize]) -> Vec<usize> {
+    let mut rows = rows.to_vec();
+    rows.sort_unstable();
+    rows
+}
+
+/// The groups of a grouping result as edited by the reviewer. Every edit can
+/// be undone.
+#[derive(Debug, Clone, Default)]
+pub struct Review {
+    groups: Vec<ReviewedGroup>,
+    /// The pairs of rows the reviewer decided on, to be kept for later runs.
+    decisions: RowConstraints,
+    /// The groups and decisions before every edit, the latest last.
+    undo: Vec<(Vec<ReviewedGroup>, RowConstraints)>,
+    /// Whether the last edit is a [`regroup`](Self::regroup), undone together
+    /// with the regroupings right before it.
+    regrouped: bool,
+}
+
+impl Review {
+    pub fn new(groups: Vec<Vec<usize>>) -> Self {
+        Self {
+            groups: groups
+                .into_iter()
+                .map(|rows| ReviewedGroup {
+                    rows,
+                    status: GroupStatus::Unreviewed,
+                })
+                .collect(),
+            decisions: RowConstraints::default(),
+            undo: Vec::new(),
+            regrouped: false,
+        }
+    }
+
+    pub fn groups(&self) -> &[ReviewedGroup] {
+        &self.groups
+    }
+
+    /// The row indices of every group, in the reviewed state.
+    pub fn row_groups(&self) -> Vec<Vec<usize>> {
+        self.groups.iter().map(|g| g.rows.clone()).collect()
+    }
+
+    /// The must-link and cannot-link pairs of rows following from the edits.
+    pub fn decisions(&self) -> &RowConstraints {
+        &self.decisions
+    }
+
+    fn save(&mut self) {
+        self.undo
+            .push((self.groups.clone(), self.decisions.clone()));
+        self.regrouped = false;
+    }
+
+    /// Replace the groups by `groups`, found again with other settings while
+    /// honouring the decisions so far, which are kept. Groups found again keep
+    /// their status. Undoing brings back the groups before.
+    pub fn regroup(&mut self, groups: Vec<Vec<usize>>) {
+        if !self.regrouped {
+            self.save();
+        }
+        let statuses: HashMap<Vec<usize>, GroupStatus> = self
+            .groups
+            .iter()
+            .filter(|g| g.status != GroupStatus::Unreviewed)
+            .map(|g| (sorted(&g.rows), g.status))
+            .collect();
+        self.groups = groups
+            .into_iter()
+            .map(|rows| ReviewedGroup {
+                status: statuses.get(&sorted(&rows)).copied().unwrap_or_default(),
+                rows,
+            })
+            .collect();
+        self.regrouped = true;
+    }
+
+    /// Link every row of the `group`th group to its first row.
+    fn link_rows(&mut self, group: usize) {
+        let rows = &self.groups[group].rows;
+        for &row in &rows[1..] {
+            self.decisions.add_must_link(rows[0], row);
+        }
+    }
+
+    /// Mark the rows of the `group`th group as duplicates of each other.
+    pub fn confirm(&mut self, group: usize) {
+        if self.groups[group].status == GroupStatus::Confirmed {
+            return;
+        }
+        self.save();
+        self.groups[group].status = GroupStatus::Confirmed;
+        self.link_rows(group);
+    }
+
+    /// Take `row` out of the `group`th group, it becomes a group of its own
+    /// right after.
+    pub fn remove_row(&mut self, group: usize, row: usize) {
+        let pos = match self.groups[group].rows.iter().position(|r| *r == row) {
+            Some(pos) if self.groups[group].rows.len() > 1 => pos,
+            _ => return,
+        };
+        self.save();
+        self.groups[group].rows.remove(pos);
+        for &other in &self.groups[group].rows {
+            self.decisions.add_cannot_link(row, other);
+        }
+        self.groups.insert(
+            group + 1,
+            ReviewedGroup {
+                rows: vec![row],
+                status: GroupStatus::NotDuplicate,
+            },
+        );
+    }
+
+    /// Merge `groups` into the first of them, which keeps its place. The
+    /// merged group is confirmed.
+    pub fn merge(&mut self, groups: &[usize]) {
+        let mut groups = groups.to_vec();
+        groups.sort_unstable();
+        groups.dedup();
+        if groups.len() < 2 {
+            return;
+        }
+        self.save();
+        let into = groups[0];
+        let merged: Vec<usize> = groups[1..]
+            .iter()
+            .flat_map(|&other| self.groups[other].rows.clone())
+            .collect();
+        self.groups[into].rows.extend(merged);
+        self.groups[into].status = GroupStatus::Confirmed;
+        // Remove from the back so the indices stay valid
+        for &other in groups[1..].iter().rev() {
+            self.groups.remove(other);
+        }
+        self.link_rows(into);
+    }
+
+    /// Split the `group`th group into groups of a single row, its rows are not
+    /// duplicates of each other.
+    pub fn mark_not_duplicate(&mut self, group: usize) {
+        if self.groups[group].rows.len() < 2 {
+            if self.groups[group].status != GroupStatus::NotDuplicate {
+                self.save();
+                self.groups[group].status = GroupStatus::NotDuplicate;
+            }
+            return;
+        }
+        self.save();
+        let rows = &self.groups[group].rows;
+        for (i, &a) in rows.iter().enumerate() {
+            for &b in &rows[i + 1..] {
+                self.decisions.add_cannot_link(a, b);
+            }
+        }
+        let split: Vec<ReviewedGroup> = self.groups[group]
+            .rows
+            .iter()
+            .map(|&row| ReviewedGroup {
+                rows: vec![row],
+                status: GroupStatus::NotDuplicate,
+            })
+            .collect();
+        self.groups.splice(group..group + 1, split);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Revert the last edit, returns whether there was one.
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some((groups, decisions)) => {
+                self.groups = groups;
+                self.decisions = decisions;
+                self.regrouped = false;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regroup_keeps_edits_and_undoes_at_once() {
+        let mut review = Review::new(vec![vec![0, 1, 2], vec![3, 4], vec![5]]);
+        review.confirm(1);
+        review.remove_row(0, 2);
+        let edited = review.groups().to_vec();
+
+        // Two steps of the similarity slider
+        review.regroup(vec![vec![0, 1, 5], vec![2], vec![4, 3]]);
+        review.regroup(vec![vec![0, 1], vec![2], vec![4, 3], vec![5]]);
+        let statuses: Vec<GroupStatus> = review.groups().iter().map(|g| g.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                GroupStatus::Unreviewed,
+                GroupStatus::NotDuplicate,
+                GroupStatus::Confirmed,
+                GroupStatus::Unreviewed
+            ]
+        );
+        assert!(review.decisions().must_link.contains(&(3, 4)));
+        assert!(review.decisions().cannot_link.contains(&(0, 2)));
+
+        assert!(review.undo());
+        assert_eq!(review.groups(), &edited[..]);
+        assert!(review.undo());
+        assert!(review.undo());
+        assert!(!review.can_undo());
+    }
+}
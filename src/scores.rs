@@ -0,0 +1,253 @@
+//! Pairwise similarities kept between groupings of the same table, so a new
+//! similarity threshold regroups the rows without comparing them again.
+//!
+//! The similar pairs are scored once down to a floor below the threshold,
+//! [`SCORE_MARGIN`] below it when grouping from the user interface.
+//! Grouping at any threshold above the floor only drops the pairs scoring
+//! below it, which gives the same groups as comparing the rows from scratch.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+use crate::edit_distance::LengthUnit;
+use crate::grouping::{
+    compared_columns, group_neighbours, similar_rows, ColumnMatch, GroupingConfig, Table,
+};
+use crate::normalize::Normalization;
+use crate::phonetic::Phonetic;
+use crate::progress::Progress;
+
+/// How far below the threshold pairs are scored, in percent points, so that
+/// lowering it that far regroups without comparing again. A fixed floor would
+/// make grouping at a high threshold score as many pairs as at a low one.
+pub const SCORE_MARGIN: f64 = 10.0;
+
+/// Everything the scores depend on. The threshold, clustering and
+/// constraints only change how the scores are grouped.
+#[derive(Debug, Clone, PartialEq)]
+struct ScoreKey {
+    file: PathBuf,
+    rows: usize,
+    /// Hash of the compared values, as the file may have changed on disk.
+    values: u64,
+    columns: Vec<ColumnMatch>,
+    unit: LengthUnit,
+    case_sensitive: bool,
+    normalization: Normalization,
+    phonetic: Phonetic,
+}
+
+impl ScoreKey {
+    fn new(file: PathBuf, rows: usize, values: u64, config: &GroupingConfig) -> Self {
+        Self {
+            file,
+            rows,
+            values,
+            columns: config.column_matches(),
+            unit: config.unit,
+            case_sensitive: config.case_sensitive,
+            normalization: config.normalization.clone(),
+            phonetic: config.phonetic,
+        }
+    }
+}
+
+/// Hash of the values of the compared columns, column by column.
+fn hash_values<'a, C, V>(columns: C) -> u64
+where
+    C: IntoIterator<Item = V>,
+    V: IntoIterator<Item = &'a str>,
+{
+    let mut hasher = DefaultHasher::new();
+    for column in columns {
+        for value in column {
+            value.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// The similar pairs of rows of a table, with their similarity, down to a
+/// floor.
+pub struct ScoreCache {
+    key: ScoreKey,
+    floor: f64,
+    /// For every row, the other rows reaching `floor` with their similarity,
+    /// sorted by row index.
+    neighbours: Vec<Vec<(usize, f64)>>,
+}
+
+impl ScoreCache {
+    /// Compare the rows of `table` as set by `config`, keeping the pairs
+    /// reaching `floor`, or the similarity of `config` when it is lower.
+    pub fn new(table: &Table, config: &GroupingConfig, floor: f64) -> Self {
+        Self::from_columns(
+            table.file.clone(),
+            &compared_columns(table, config),
+            config,
+            floor,
+            &Progress::new(),
+        )
+        .expect("scoring is not cancelled")
+    }
+
+    /// Same as [`new`](Self::new), for the already extracted
+    /// [`compared_columns`] of the table read from `file`, counting the
+    /// checked pairs of rows in `progress`. `None` when `progress` is
+    /// cancelled before every row is compared.
+    pub fn from_columns(
+        file: PathBuf,
+        columns: &[Vec<String>],
+        config: &GroupingConfig,
+        floor: f64,
+        progress: &Progress,
+    ) -> Option<Self> {
+        let floor = floor.min(config.similarity);
+        let scored = GroupingConfig {
+            similarity: floor,
+            ..config.clone()
+        };
+        let rows = columns.first().map_or(0, Vec::len);
+        let values = hash_values(columns.iter().map(|c| c.iter().map(String::as_str)));
+        Some(Self {
+            key: ScoreKey::new(file, rows, values, config),
+            floor,
+            neighbours: similar_rows(columns, &scored, progress)?,
+        })
+    }
+
+    /// The lowest similarity kept, in percent.
+    pub fn floor(&self) -> f64 {
+        self.floor
+    }
+
+    /// The number of pairs kept.
+    pub fn pairs(&self) -> usize {
+        self.neighbours.iter().map(Vec::len).sum::<usize>() / 2
+    }
+
+    /// Whether [`group`](Self::group) can group `table` as set by `config`:
+    /// the same table and compared columns with the same values, prepared
+    /// the same way, at a threshold no lower than the floor.
+    pub fn covers(&self, table: &Table, config: &GroupingConfig) -> bool {
+        if config.similarity < self.floor || config.check_columns(table).is_err() {
+            return false;
+        }
+        let values = hash_values(
+            config
+                .column_matches()
+                .iter()
+                .map(|m| table.rows.iter().map(move |row| row[m.col_idx].as_str())),
+        );
+        self.key == ScoreKey::new(table.file.clone(), table.rows.len(), values, config)
+    }
+
+    /// The groups of the rows at the similarity of `config`, the same as
+    /// [`crate::grouping::group`] gives when the cache
+    /// [`covers`](Self::covers) the table and `config`.
+    pub fn group(&self, config: &GroupingConfig) -> Vec<Vec<usize>> {
+        let neighbours = self
+            .neighbours
+            .iter()
+            .map(|n| {
+                n.iter()
+                    .copied()
+                    .filter(|&(_, score)| score >= config.similarity)
+                    .collect()
+            })
+            .collect();
+        group_neighbours(neighbours, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::Clustering;
+    use crate::edit_distance::Metric;
+    use crate::grouping::group;
+
+    fn table() -> Table {
+        let names = [
+            "Smith", "Smyth", "Smithe", "Jones", "Jonas", "Jone", "Brown", "Braun", "Browne",
+            "Schmidt", "Schmitt", "Smit", "Johnson", "Jonson", "Johnsen",
+        ];
+        let cities = ["Berlin", "Berlyn", "Bern", "Paris", "Pariss", "Rome"];
+        let rows = (0..150)
+            .map(|i| {
+                let suffix = if i % 5 == 0 { "x" } else { "" };
+                vec![
+                    format!("{}{}", names[i * 7 % names.len()], suffix),
+                    cities[i * 3 % cities.len()].to_string(),
+                ]
+            })
+            .collect();
+        Table {
+            file: PathBuf::from("people.csv"),
+            headers: vec![String::from("name"), String::from("city")],
+            rows,
+        }
+    }
+
+    #[test]
+    fn cached_groups_match_grouping_from_scratch() {
+        let table = table();
+        for metric in Metric::ALL {
+            for clustering in Clustering::ALL {
+                for columns in [
+                    Vec::new(),
+                    vec![
+                        ColumnMatch {
+                            col_idx: 0,
+                            metric,
+                            weight: 2,
+                            similarity: 60.0,
+                        },
+                        ColumnMatch {
+                            col_idx: 1,
+                            metric: Metric::Levenshtein,
+                            weight: 1,
+                            similarity: 0.0,
+                        },
+                    ],
+                ] {
+                    let mut config = GroupingConfig {
+                        metric,
+                        clustering,
+                        columns,
+                        similarity: 75.0,
+                        ..Default::default()
+                    };
+                    let scores = ScoreCache::new(&table, &config, 70.0);
+                    for similarity in [70.0, 72.5, 75.0, 80.0, 83.33, 90.0, 100.0] {
+                        config.similarity = similarity;
+                        assert!(scores.covers(&table, &config));
+                        assert_eq!(
+                            scores.group(&config),
+                            group(&table, &config),
+                            "{:?} {:?} at {}",
+                            metric,
+                            clustering,
+                            similarity
+                        );
+                    }
+                    config.similarity = 60.0;
+                    assert!(!scores.covers(&table, &config));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn changed_values_are_not_covered() {
+        let mut table = table();
+        let config = GroupingConfig::default();
+        let scores = ScoreCache::new(&table, &config, 90.0);
+        assert!(scores.covers(&table, &config));
+        table.rows[3][1] = String::from("Madrid");
+        assert!(scores.covers(&table, &config), "city is not compared");
+        table.rows[3][0] = String::from("Miller");
+        assert!(!scores.covers(&table, &config));
+    }
+}
@@ -0,0 +1,177 @@
+//! Reading a sheet of an Excel or OpenDocument workbook into a [`Table`].
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use calamine::{open_workbook_auto, Cell, Data, DataType, Range, Reader, Sheets};
+
+use crate::grouping::Table;
+
+/// The extensions of the workbooks that can be read.
+pub const SPREADSHEET_EXTENSIONS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+
+/// Whether `path` is a workbook, by its extension.
+pub fn is_spreadsheet(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| SPREADSHEET_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Which part of a workbook is read.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct SheetOptions {
+    /// The name of the sheet, the first sheet when empty.
+    pub sheet: String,
+    /// The zero based row holding the column names, the rows above it are
+    /// skipped. A blank row means the first non-blank one. Without it the
+    /// columns are named `Column 1`, `Column 2`...
+    pub header_row: Option<usize>,
+}
+
+impl Default for SheetOptions {
+    fn default() -> Self {
+        Self {
+            sheet: String::new(),
+            header_row: Some(0),
+        }
+    }
+}
+
+fn invalid_data(e: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// The names of the sheets of the workbook at `path`, in order.
+pub fn sheet_names(path: &Path) -> Result<Vec<String>, std::io::Error> {
+    Ok(open_workbook_auto(path)
+        .map_err(invalid_data)?
+        .sheet_names())
+}
+
+/// The text of a cell, dates as `2023-01-31` or `2023-01-31 08:00:00`.
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::String(s) => s.clone(),
+        Data::DateTime(dt) if dt.is_datetime() => match dt.as_datetime() {
+            Some(dt) => {
+                let text = dt.to_string();
+                match text.strip_suffix(" 00:00:00") {
+                    Some(date) => date.to_owned(),
+                    None => text,
+                }
+            }
+            None => cell.to_string(),
+        },
+        _ => cell.to_string(),
+    }
+}
+
+/// The cells of `sheet` of `workbook`. From xlsx workbooks only the rows up
+/// to `header_row` and `limit` non-blank rows after it are read, other
+/// workbooks have no lazy reader.
+fn sheet_range(
+    workbook: &mut Sheets<BufReader<File>>,
+    sheet: &str,
+    header_row: Option<usize>,
+    limit: usize,
+) -> Result<Range<Data>, std::io::Error> {
+    let xlsx = match workbook {
+        Sheets::Xlsx(xlsx) if limit < usize::MAX => xlsx,
+        _ => return workbook.worksheet_range(sheet).map_err(invalid_data),
+    };
+    let mut reader = xlsx.worksheet_cells_reader(sheet).map_err(invalid_data)?;
+    let mut cells = Vec::new();
+    // The rows after the header row read so far, the last one being read
+    let mut rows = 0;
+    let mut last_row = None;
+    let mut first_row = None;
+    while let Some(cell) = reader.next_cell().map_err(invalid_data)? {
+        // Blank cells are left out, as by `worksheet_range`
+        if cell.get_value().is_empty() {
+            continue;
+        }
+        let (row, col) = cell.get_position();
+        // A blank header row means the first non-blank one
+        let first_row = *first_row.get_or_insert(row) as usize;
+        let after_header = header_row.map_or(true, |h| row as usize > h.max(first_row));
+        if after_header && last_row != Some(row) {
+            if rows == limit {
+                break;
+            }
+            rows += 1;
+            last_row = Some(row);
+        }
+        cells.push(Cell::new((row, col), Data::from(cell.get_value().clone())));
+    }
+    Ok(Range::from_sparse(cells))
+}
+
+/// Read a sheet of the workbook at `path`.
+pub fn read_sheet(path: PathBuf, options: &SheetOptions) -> Result<Table, std::io::Error> {
+    read_sheet_rows(path, options, usize::MAX)
+}
+
+/// The first `rows` rows of a sheet, to preview the options before loading.
+/// Only the rows previewed are read from xlsx workbooks, other workbooks are
+/// read whole.
+pub fn preview_sheet(
+    path: PathBuf,
+    options: &SheetOptions,
+    rows: usize,
+) -> Result<Table, std::io::Error> {
+    read_sheet_rows(path, options, rows)
+}
+
+fn read_sheet_rows(
+    path: PathBuf,
+    options: &SheetOptions,
+    limit: usize,
+) -> Result<Table, std::io::Error> {
+    let mut workbook = open_workbook_auto(&path).map_err(invalid_data)?;
+    let sheet = if options.sheet.is_empty() {
+        workbook
+            .sheet_names()
+            .into_iter()
+            .next()
+            .ok_or_else(|| invalid_data("The workbook has no sheet"))?
+    } else {
+        options.sheet.clone()
+    };
+    let range = sheet_range(&mut workbook, &sheet, options.header_row, limit)?;
+
+    // The range starts at the first non-blank row
+    let first_row = range.start().map_or(0, |(row, _)| row as usize);
+    let mut records = range.rows().map(|r| r.iter().map(cell_text).collect());
+    let mut headers: Vec<String> = Vec::new();
+    if let Some(header_row) = options.header_row {
+        headers = records
+            .by_ref()
+            .nth(header_row.saturating_sub(first_row))
+            .unwrap_or_default();
+    }
+    let mut rows: Vec<Vec<String>> = records
+        .filter(|r: &Vec<String>| r.iter().any(|v| !v.is_empty()))
+        .take(limit)
+        .collect();
+
+    // Every row has a value for every column, as in csv files
+    let width = rows.iter().map(Vec::len).fold(headers.len(), usize::max);
+    for idx in headers.len()..width {
+        headers.push(format!("Column {}", idx + 1));
+    }
+    for (idx, header) in headers.iter_mut().enumerate() {
+        if header.is_empty() {
+            *header = format!("Column {}", idx + 1);
+        }
+    }
+    for row in &mut rows {
+        row.resize(width, String::new());
+    }
+    Ok(Table {
+        headers,
+        rows,
+        file: path,
+    })
+}
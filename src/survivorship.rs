@@ -0,0 +1,202 @@
+//! Merging every group into a single surviving row, the golden record, with a
+//! survivorship rule per column.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::encoding::TextEncoding;
+use crate::grouping::{write_encoded, Table};
+
+/// How the value of a column is picked among the rows of a group. Blank values
+/// are skipped by every rule, a column blank in all rows stays blank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum Survivorship {
+    /// The value of most rows, the first of them on ties.
+    MostFrequent,
+    /// The value with the most characters, the first of them on ties.
+    Longest,
+    /// The value of the first row, in group order.
+    #[default]
+    FirstNonEmpty,
+    /// The value of the row with the latest date in [`MergeConfig::date_column`].
+    Newest,
+    /// Every distinct value, in group order, joined by [`MergeConfig::separator`].
+    ConcatenateUnique,
+}
+
+impl Survivorship {
+    pub const ALL: [Survivorship; 5] = [
+        Survivorship::MostFrequent,
+        Survivorship::Longest,
+        Survivorship::FirstNonEmpty,
+        Survivorship::Newest,
+        Survivorship::ConcatenateUnique,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Survivorship::MostFrequent => "Most frequent",
+            Survivorship::Longest => "Longest",
+            Survivorship::FirstNonEmpty => "First non-empty",
+            Survivorship::Newest => "Newest",
+            Survivorship::ConcatenateUnique => "Concatenate unique",
+        }
+    }
+}
+
+/// How the rows of a group are merged.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct MergeConfig {
+    /// The rule of every column by index, columns without one use
+    /// [`Survivorship::FirstNonEmpty`].
+    pub rules: Vec<Survivorship>,
+    /// The column dating the rows for [`Survivorship::Newest`]. Dates are read
+    /// year first, like `2023-01-31`, `2023/1/31 08:00` or `2023年1月31日`,
+    /// other values are older than any date. Without it `Newest` picks the
+    /// first non-empty value.
+    pub date_column: Option<usize>,
+    /// Put between the values of [`Survivorship::ConcatenateUnique`].
+    pub separator: String,
+}
+
+impl Default for MergeConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            date_column: None,
+            separator: String::from("; "),
+        }
+    }
+}
+
+impl MergeConfig {
+    pub fn rule(&self, col_idx: usize) -> Survivorship {
+        self.rules.get(col_idx).copied().unwrap_or_default()
+    }
+}
+
+/// Sort key of a year first date: its numbers in order.
+fn date_key(value: &str) -> Option<Vec<u64>> {
+    let numbers = value
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|n| !n.is_empty())
+        .map(|n| n.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    let year = value
+        .trim_start()
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?;
+    if year.len() == 4 {
+        Some(numbers)
+    } else {
+        None
+    }
+}
+
+/// The surviving row of the rows `group` of `table`.
+pub fn merge_group(table: &Table, group: &[usize], config: &MergeConfig) -> Vec<String> {
+    let newest_first: Vec<usize> = match config.date_column {
+        Some(date_column) if date_column < table.headers.len() => {
+            let mut rows = group.to_vec();
+            // Stable, so rows of the same date keep the group order
+            rows.sort_by_cached_key(|&r| Reverse(date_key(&table.rows[r][date_column])));
+            rows
+        }
+        _ => group.to_vec(),
+    };
+
+    (0..table.headers.len())
+        .map(|col_idx| {
+            let values = |rows: &[usize]| -> Vec<&str> {
+                rows.iter()
+                    .map(|&r| table.rows[r][col_idx].as_str())
+                    .filter(|v| !v.trim().is_empty())
+                    .collect()
+            };
+            let values = match config.rule(col_idx) {
+                Survivorship::Newest => values(&newest_first),
+                _ => values(group),
+            };
+            match config.rule(col_idx) {
+                Survivorship::MostFrequent => {
+                    let mut counts: HashMap<&str, usize> = HashMap::new();
+                    for value in &values {
+                        *counts.entry(value).or_default() += 1;
+                    }
+                    // The first of the most frequent values
+                    let max = counts.values().copied().max().unwrap_or_default();
+                    values.into_iter().find(|v| counts[v] == max)
+                }
+                Survivorship::Longest => {
+                    let max = values.iter().map(|v| v.chars().count()).max();
+                    values.into_iter().find(|v| Some(v.chars().count()) == max)
+                }
+                Survivorship::FirstNonEmpty | Survivorship::Newest => values.first().copied(),
+                Survivorship::ConcatenateUnique => {
+                    let mut unique: Vec<&str> = Vec::new();
+                    for value in values {
+                        if !unique.contains(&value) {
+                            unique.push(value);
+                        }
+                    }
+                    return unique.join(&config.separator);
+                }
+            }
+            .unwrap_or_default()
+            .to_owned()
+        })
+        .collect()
+}
+
+/// The surviving row of every group.
+pub fn merge_groups(
+    table: &Table,
+    groups: &[Vec<usize>],
+    config: &MergeConfig,
+) -> Vec<Vec<String>> {
+    groups
+        .iter()
+        .map(|group| merge_group(table, group, config))
+        .collect()
+}
+
+/// Write the headers of `table` then the surviving row of every group, a
+/// deduplicated copy of the table, in `encoding`. Rows in none of `groups`,
+/// like the singletons left out by [`crate::GroupingConfig::drop_singletons`],
+/// are written as they are. Rows are in the order of the first row of their
+/// group. Returns the number of rows written, without the headers.
+pub fn write_merged(
+    csv: &Path,
+    table: &Table,
+    groups: &[Vec<usize>],
+    config: &MergeConfig,
+    encoding: TextEncoding,
+) -> Result<usize, std::io::Error> {
+    let mut grouped = vec![false; table.rows.len()];
+    for &row in groups.iter().flatten() {
+        grouped[row] = true;
+    }
+    let mut all_groups: Vec<Vec<usize>> = groups
+        .iter()
+        .filter(|group| !group.is_empty())
+        .cloned()
+        .chain(
+            (0..table.rows.len())
+                .filter(|&r| !grouped[r])
+                .map(|r| vec![r]),
+        )
+        .collect();
+    all_groups.sort_by_key(|group| group.iter().copied().min());
+
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(Vec::new());
+    wtr.write_record(&table.headers)?;
+    for group in &all_groups {
+        wtr.write_record(merge_group(table, group, config))?;
+    }
+    write_encoded(csv, wtr, encoding)?;
+    Ok(all_groups.len())
+}
@@ -0,0 +1,96 @@
+//! Exporting a grouping result to an Excel workbook, with the groups told apart
+//! by their fill colour and a summary sheet.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rust_xlsxwriter::{Color, Format, Workbook, XlsxError};
+
+use crate::grouping::{seed_similarities, GroupStats, GroupingConfig, Table};
+
+/// Fill colours of the rows of even and odd groups.
+const GROUP_FILLS: [u32; 2] = [0xDDEBF7, 0xFFF2CC];
+
+fn io_error(e: XlsxError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
+/// Write the rows of `table` group by group to a workbook. Every row has its
+/// group, its row index and its similarity to the first row of its group in
+/// front, the rows of a group share a fill colour. A second sheet sums the
+/// groups up.
+pub fn write_xlsx(
+    path: &Path,
+    table: &Table,
+    groups: &[Vec<usize>],
+    config: &GroupingConfig,
+) -> Result<(), std::io::Error> {
+    write_workbook(path, table, groups, config).map_err(io_error)
+}
+
+fn write_workbook(
+    path: &Path,
+    table: &Table,
+    groups: &[Vec<usize>],
+    config: &GroupingConfig,
+) -> Result<(), XlsxError> {
+    let mut workbook = Workbook::new();
+    let bold = Format::new().set_bold();
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Groups")?;
+    let headers = ["Group", "Index", "Similarity"]
+        .into_iter()
+        .chain(table.headers.iter().map(|h| h.as_str()));
+    sheet.write_row_with_format(0, 0, headers, &bold)?;
+
+    let similarities = seed_similarities(table, groups, config);
+    let mut row = 1;
+    for (g, group) in groups.iter().enumerate() {
+        let fill = Format::new().set_background_color(Color::RGB(GROUP_FILLS[g % 2]));
+        let percent = fill.clone().set_num_format("0.00");
+        for (r_idx, similarity) in group.iter().zip(&similarities[g]) {
+            sheet.write_number_with_format(row, 0, g as f64, &fill)?;
+            sheet.write_number_with_format(row, 1, *r_idx as f64, &fill)?;
+            sheet.write_number_with_format(row, 2, *similarity, &percent)?;
+            for (col, value) in table.rows[*r_idx].iter().enumerate() {
+                sheet.write_string_with_format(row, 3 + col as u16, value, &fill)?;
+            }
+            row += 1;
+        }
+    }
+    let last_col = 2 + table.headers.len() as u16;
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofilter(0, 0, row - 1, last_col)?;
+    sheet.autofit();
+
+    let stats = GroupStats::new(groups, table.rows.len());
+    let summary = workbook.add_worksheet();
+    summary.set_name("Summary")?;
+    let counts = [
+        ("Rows", table.rows.len()),
+        ("Groups", groups.len()),
+        ("Duplicate groups", stats.duplicate_groups),
+        ("Duplicate rows", stats.duplicate_rows),
+        ("Unique rows", stats.unique_rows),
+    ];
+    for (row, (name, count)) in counts.iter().enumerate() {
+        summary.write_string_with_format(row as u32, 0, *name, &bold)?;
+        summary.write_number(row as u32, 1, *count as f64)?;
+    }
+
+    // How many groups there are of every size
+    let mut sizes: BTreeMap<usize, usize> = BTreeMap::new();
+    for group in groups {
+        *sizes.entry(group.len()).or_default() += 1;
+    }
+    let first = counts.len() as u32 + 1;
+    summary.write_row_with_format(first, 0, ["Group size", "Groups"], &bold)?;
+    for (row, (size, count)) in sizes.iter().enumerate() {
+        summary.write_number(first + 1 + row as u32, 0, *size as f64)?;
+        summary.write_number(first + 1 + row as u32, 1, *count as f64)?;
+    }
+    summary.autofit();
+
+    workbook.save(path)
+}